@@ -1,20 +1,211 @@
 use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use std::path::PathBuf;
+use vkmsctl::attribute::ENABLE_RETRIES;
+use vkmsctl::device::VKMS_SUBSYSTEM;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
     /// Directory were ConfigFS is mounted/needs to be mounted.
-    #[arg(long, default_value = "/config")]
+    #[arg(long, env = "VKMSCTL_CONFIGFS_PATH", default_value = "/config")]
     pub configfs_path : String,
 
+    /// Accept a relative --configfs-path, resolved from the current directory.
+    #[arg(long)]
+    pub allow_relative_configfs : bool,
+
+    /// Name of the VKMS directory inside ConfigFS.
+    #[arg(long, default_value = VKMS_SUBSYSTEM)]
+    pub subsystem : String,
+
+    /// Log more messages, can be repeated (-vv).
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose : u8,
+
+    /// Only log warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet : bool,
+
+    /// Print each cause of an error on its own line. Implied by -vv.
+    #[arg(long, global = true)]
+    pub verbose_errors : bool,
+
+    /// Log level, takes precedence over RUST_LOG, -v and -q.
+    #[arg(long, value_name = "LEVEL", global = true)]
+    pub log_level : Option<LogLevel>,
+
+    /// Hard deadline for the whole command, in seconds. Hung filesystem
+    /// operations are abandoned when it expires, not cancelled.
+    #[arg(long, value_name = "SECS", global = true)]
+    pub timeout : Option<u64>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    /// Display current configuration.
-    Config {},
+    /// Display current configuration. Stops at the first device that can't be
+    /// read unless --keep-going is given.
+    Config(ConfigArgs),
+    /// Print the value of a device attribute.
+    Get {
+        /// Name of the device.
+        device : String,
+        /// Attribute path inside the device, for example crtcs/crtc0/writeback.
+        attribute : String,
+        #[command(flatten)]
+        output : OutputArgs,
+    },
+    /// Change the value of a device attribute.
+    Set {
+        /// Name of the device.
+        device : String,
+        /// Attribute path inside the device, for example crtcs/crtc0/writeback.
+        attribute : String,
+        /// New value, for example true or disconnected.
+        value : String,
+        /// Times to retry enabling the device if the kernel is busy.
+        #[arg(long, default_value_t = ENABLE_RETRIES)]
+        enable_retries : u32,
+    },
+    /// Report which VKMS features are available.
+    Capabilities {
+        #[command(flatten)]
+        output : OutputArgs,
+    },
+    /// Write every device as JSON or RON. Devices that can't be read are skipped
+    /// unless --fail-fast is given.
+    DumpAll {
+        #[arg(long, value_enum, default_value_t)]
+        format : DataFormat,
+        #[command(flatten)]
+        batch : BatchArgs,
+        #[command(flatten)]
+        output : OutputArgs,
+    },
+    /// Compare a device with its JSON description, as written by dump-all, and
+    /// fail if they differ.
+    Diff {
+        /// Name of the device.
+        name : String,
+        /// JSON file with the device, or with a list of devices.
+        config : PathBuf,
+    },
+    /// Remove a VKMS device.
+    Remove {
+        /// Name of the device to remove.
+        name : String,
+        /// Print the operations that would remove the device, in order, without
+        /// running them.
+        #[arg(long, conflicts_with = "wait")]
+        dry_run : bool,
+        /// Wait until the kernel has finished removing the device.
+        #[arg(long)]
+        wait : bool,
+        /// Maximum time to wait with --wait, in seconds.
+        #[arg(long, value_name = "SECS", default_value_t = 5, requires = "wait")]
+        wait_timeout : u64,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConfigArgs {
+    /// Don't warn about planes without possible CRTCs.
+    #[arg(long)]
+    pub allow_orphan_planes : bool,
+
+    /// Don't warn about devices without CRTCs.
+    #[arg(long)]
+    pub allow_no_crtcs : bool,
+
+    /// Print resource totals across all devices instead of each device.
+    #[arg(long)]
+    pub summary : bool,
+
+    /// Also warn about unusual plane types per CRTC, e.g. two primary planes.
+    #[arg(long)]
+    pub lint : bool,
+
+    /// Fail if any device has configuration warnings.
+    #[arg(long)]
+    pub deny_warnings : bool,
+
+    /// Only print these components, for example planes,crtcs.
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "summary")]
+    pub only : Vec<Component>,
+
+    /// Print the devices in a machine-readable format instead.
+    #[arg(long, conflicts_with = "summary")]
+    pub format : Option<DataFormat>,
+
+    #[command(flatten)]
+    pub batch : BatchArgs,
+
+    #[command(flatten)]
+    pub output : OutputArgs,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level : LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Component groups of a device.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    Planes,
+    Crtcs,
+    Encoders,
+    Connectors,
+}
+
+/// Machine-readable output formats.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum DataFormat {
+    #[default]
+    Json,
+    Ron,
+}
+
+/// Error handling of commands processing multiple devices. Each command
+/// documents which mode is the default.
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    /// Stop at the first device that fails.
+    #[arg(long, conflicts_with = "keep_going")]
+    pub fail_fast : bool,
+
+    /// Process every device and report the failures at the end.
+    #[arg(long)]
+    pub keep_going : bool,
+}
+
+/// Destination of read-only commands.
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// Write the output to this file instead of stdout.
+    #[arg(short, long)]
+    pub output : Option<PathBuf>,
 }
 
 pub fn parse() -> Args {
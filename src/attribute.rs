@@ -0,0 +1,275 @@
+use crate::device::{ConnectorStatus, PlaneKind, VkmsDevice};
+use crate::error::with_context;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Default number of times enabling a device is retried, see `retry_transient`.
+pub const ENABLE_RETRIES : u32 = 3;
+
+/// Delay before the first retry, doubled after every attempt.
+const RETRY_BACKOFF : Duration = Duration::from_millis(50);
+
+/// Values of the boolean attributes (`enabled`, `writeback`).
+const BOOL_VALUES : [(&str, &str); 2] = [("0", "false"), ("1", "true")];
+
+/// Attributes whose raw value has a human-readable form.
+enum KnownAttribute {
+    Bool,
+    PlaneType,
+    ConnectorStatus,
+}
+
+impl KnownAttribute {
+    fn from_path(attribute : &Path) -> Option<Self> {
+        let components : Vec<_> = attribute.iter().filter_map(|c| c.to_str()).collect();
+        match components.as_slice() {
+            ["enabled"] | ["crtcs", _, "writeback"] => Some(KnownAttribute::Bool),
+            ["planes", _, "type"] => Some(KnownAttribute::PlaneType),
+            ["connectors", _, "status"] => Some(KnownAttribute::ConnectorStatus),
+            _ => None,
+        }
+    }
+
+    /// Raw and human-readable forms of every valid value.
+    fn values(&self) -> Vec<(String, String)> {
+        let to_owned = |values : &[(&str, &str)]| {
+            values.iter().map(|(raw, human)| (raw.to_string(), human.to_string())).collect()
+        };
+
+        match self {
+            KnownAttribute::Bool => to_owned(&BOOL_VALUES),
+            KnownAttribute::PlaneType => PlaneKind::ALL
+                .into_iter()
+                .map(|kind| (kind.to_attribute().to_string(), kind.to_string()))
+                .collect(),
+            KnownAttribute::ConnectorStatus => ConnectorStatus::ALL
+                .into_iter()
+                .map(|status| (status.to_attribute().to_string(), status.to_string()))
+                .collect(),
+        }
+    }
+
+    fn decode(&self, value : &str) -> Option<String> {
+        self.values().into_iter().find(|(raw, _)| raw == value).map(|(_, human)| human)
+    }
+
+    /// Accepts the human-readable or the raw form of a value.
+    fn encode(&self, value : &str) -> Option<String> {
+        self.values()
+            .into_iter()
+            .find(|(raw, human)| human == value || raw == value)
+            .map(|(raw, _)| raw)
+    }
+
+    /// Whether the attribute can be written while the device is enabled.
+    fn is_writable_while_enabled(&self) -> bool {
+        matches!(self, KnownAttribute::ConnectorStatus)
+    }
+}
+
+/// Describes `attribute` of the device `device` for error messages, for example
+/// "plane 'plane0' type".
+fn describe(device : &str, attribute : &str) -> String {
+    let components : Vec<_> = Path::new(attribute).iter().filter_map(|c| c.to_str()).collect();
+    match components.as_slice() {
+        ["enabled"] => format!("device '{device}' enabled"),
+        ["planes", plane, "type"] => format!("plane '{plane}' type"),
+        ["crtcs", crtc, "writeback"] => format!("CRTC '{crtc}' writeback"),
+        ["connectors", connector, "status"] => format!("connector '{connector}' status"),
+        _ => format!("{attribute} of device '{device}'"),
+    }
+}
+
+/// Writes `raw_value` to `path`, annotating errors with the attribute and the
+/// human-readable `value` being set.
+fn write_attribute(
+    path : &Path,
+    raw_value : &str,
+    description : &str,
+    value : &str,
+) -> io::Result<()> {
+    fs::write(path, raw_value).map_err(|e| {
+        with_context(e, format!("failed to set {description} to {value}"))
+    })
+}
+
+/// Whether a failed write may succeed if repeated. Only EAGAIN, EBUSY and EINTR
+/// are transient; errors caused by the configuration, like EINVAL for an
+/// invalid topology, are permanent.
+pub fn is_transient(error : &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::ResourceBusy | io::ErrorKind::Interrupted
+    )
+}
+
+/// Calls `write` up to `retries` more times, with a growing delay, while it
+/// fails with a transient error. Permanent errors are returned immediately.
+pub fn retry_transient(
+    retries : u32,
+    mut write : impl FnMut() -> io::Result<()>,
+) -> io::Result<()> {
+    let mut delay = RETRY_BACKOFF;
+    for _ in 0..retries {
+        match write() {
+            Err(e) if is_transient(&e) => {
+                log::debug!("Retrying after transient error: {e}");
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    write()
+}
+
+/// Returns the path of `attribute`, relative to the device `device`, making
+/// sure neither of them point outside of the device directory.
+fn attribute_path(
+    configfs_path : &str,
+    subsystem : &str,
+    device : &str,
+    attribute : &str,
+) -> io::Result<PathBuf> {
+    let device_path = VkmsDevice::checked_device_path(configfs_path, subsystem, device)?;
+    if !device_path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Device {device} doesn't exist"),
+        ));
+    }
+
+    let relative_path = Path::new(attribute);
+    let is_inside_device = relative_path.components().next().is_some()
+        && relative_path.components().all(|c| matches!(c, Component::Normal(_)));
+    if !is_inside_device {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid attribute path: {attribute}"),
+        ));
+    }
+
+    Ok(device_path.join(relative_path))
+}
+
+/// Reads `attribute` (for example `crtcs/crtc0/writeback`) of the device
+/// `device`. Known attributes are decoded (`true`, `cursor`, `connected`...),
+/// other attributes are returned as is.
+///
+/// Returns an `InvalidInput` error if `device` or `attribute` point outside of
+/// the device directory.
+pub fn get(
+    configfs_path : &str,
+    subsystem : &str,
+    device : &str,
+    attribute : &str,
+) -> io::Result<String> {
+    let path = attribute_path(configfs_path, subsystem, device, attribute)?;
+    let value = fs::read_to_string(path)?.trim().to_string();
+
+    let decoded =
+        KnownAttribute::from_path(Path::new(attribute)).and_then(|known| known.decode(&value));
+    Ok(decoded.unwrap_or(value))
+}
+
+/// Writes `value` to `attribute` of the device `device`. Known attributes take
+/// their human-readable value (`true`, `cursor`, `disconnected`...).
+///
+/// If the attribute can't be changed while the device is enabled, the device
+/// is disabled before writing and enabled again afterwards, even if writing
+/// failed.
+///
+/// Nothing is written if the attribute already has the value. Before enabling
+/// the device, its links are checked with
+/// `VkmsDevice::check_links`. Enabling is retried `ENABLE_RETRIES` times on
+/// transient errors.
+pub fn set(
+    configfs_path : &str,
+    subsystem : &str,
+    device : &str,
+    attribute : &str,
+    value : &str,
+) -> io::Result<()> {
+    set_with_retries(configfs_path, subsystem, device, attribute, value, ENABLE_RETRIES)
+}
+
+/// Like `set`, retrying enabling the device `enable_retries` times.
+pub fn set_with_retries(
+    configfs_path : &str,
+    subsystem : &str,
+    device : &str,
+    attribute : &str,
+    value : &str,
+    enable_retries : u32,
+) -> io::Result<()> {
+    let path = attribute_path(configfs_path, subsystem, device, attribute)?;
+    let enabled_path = attribute_path(configfs_path, subsystem, device, "enabled")?;
+
+    let known = KnownAttribute::from_path(Path::new(attribute));
+    let raw_value = match &known {
+        Some(known) => known.encode(value).ok_or_else(|| {
+            let values : Vec<_> = known.values().into_iter().map(|(_, human)| human).collect();
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid value {value} for {attribute}, expected one of: {}",
+                    values.join(", ")
+                ),
+            )
+        })?,
+        None => value.to_string(),
+    };
+
+    // Rewriting the current value could needlessly disable and enable the device
+    if fs::read_to_string(&path).is_ok_and(|current| current.trim() == raw_value) {
+        log::debug!("{attribute} of device {device} is already {value}");
+        return Ok(());
+    }
+
+    let toggle_enabled = path != enabled_path
+        && !known.as_ref().is_some_and(|known| known.is_writable_while_enabled())
+        && VkmsDevice::is_enabled(configfs_path, subsystem, device)?;
+
+    if path == enabled_path && raw_value == "1" {
+        VkmsDevice::check_links(configfs_path, subsystem, device)?;
+    }
+
+    let enabled = describe(device, "enabled");
+    if toggle_enabled {
+        write_attribute(&enabled_path, "0", &enabled, "false")?;
+    }
+
+    let human_value = known.and_then(|known| known.decode(&raw_value));
+    let write = || {
+        write_attribute(
+            &path,
+            &raw_value,
+            &describe(device, attribute),
+            human_value.as_deref().unwrap_or(value),
+        )
+    };
+    let result = if path == enabled_path && raw_value == "1" {
+        retry_transient(enable_retries, write)
+    } else {
+        write()
+    };
+
+    if toggle_enabled {
+        let enable = VkmsDevice::check_links(configfs_path, subsystem, device).and_then(|()| {
+            retry_transient(enable_retries, || {
+                write_attribute(&enabled_path, "1", &enabled, "true")
+            })
+        });
+        // If the write failed too, its error is the one worth returning
+        match (enable, &result) {
+            (Err(e), Err(_)) => log::error!("Device {device} left disabled: {e}"),
+            (Err(e), Ok(())) => return Err(e),
+            (Ok(()), _) => {}
+        }
+    }
+
+    result
+}
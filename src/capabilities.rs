@@ -0,0 +1,66 @@
+use crate::device::{self, PlaneKind, VkmsDevice};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+/// Features supported by the running VKMS.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Devices can be created through ConfigFS.
+    pub configfs : bool,
+    /// CRTCs have a `writeback` attribute.
+    pub writeback : bool,
+    /// Connectors have a `status` attribute.
+    pub connector_status : bool,
+    /// Values accepted by the plane `type` attribute.
+    pub plane_types : Vec<PlaneKind>,
+}
+
+/// Detects the features supported by VKMS by creating a throwaway device and
+/// checking which attributes the kernel creates. The device is removed before
+/// returning.
+pub fn probe(configfs_path : &str, subsystem : &str) -> io::Result<Capabilities> {
+    let subsystem_path = Path::new(configfs_path).join(subsystem);
+    if !subsystem_path.is_dir() {
+        return Ok(Capabilities {
+            configfs: false,
+            writeback: false,
+            connector_status: false,
+            plane_types: Vec::new(),
+        });
+    }
+
+    let name = format!("vkmsctl-probe-{}", process::id());
+    fs::create_dir(subsystem_path.join(&name))?;
+
+    let capabilities = probe_device(&subsystem_path.join(&name));
+    device::remove(configfs_path, subsystem, &name)?;
+    capabilities
+}
+
+fn probe_device(path : &Path) -> io::Result<Capabilities> {
+    let crtc_path = path.join("crtcs/probe");
+    fs::create_dir(&crtc_path)?;
+    let writeback = VkmsDevice::crtc_writeback_path(path, "probe").exists();
+
+    let connector_path = path.join("connectors/probe");
+    fs::create_dir(&connector_path)?;
+    let connector_status = VkmsDevice::connector_status_path(path, "probe").exists();
+
+    let plane_path = path.join("planes/probe");
+    fs::create_dir(&plane_path)?;
+    let plane_types = PlaneKind::ALL
+        .into_iter()
+        .filter(|kind| {
+            fs::write(VkmsDevice::plane_type_path(path, "probe"), kind.to_attribute()).is_ok()
+        })
+        .collect();
+
+    Ok(Capabilities {
+        configfs: true,
+        writeback,
+        connector_status,
+        plane_types,
+    })
+}
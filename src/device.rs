@@ -0,0 +1,863 @@
+use crate::error::with_context;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the VKMS directory in ConfigFS.
+pub const VKMS_SUBSYSTEM : &str = "vkms";
+
+/// How often `wait_removed` checks whether the device is gone.
+const REMOVE_POLL_INTERVAL : Duration = Duration::from_millis(10);
+
+/// Type of a plane, as stored in its `type` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaneKind {
+    Overlay,
+    Primary,
+    Cursor,
+}
+
+impl PlaneKind {
+    pub const ALL : [PlaneKind; 3] = [PlaneKind::Overlay, PlaneKind::Primary, PlaneKind::Cursor];
+
+    /// Value of the `type` attribute for this kind.
+    pub fn to_attribute(self) -> &'static str {
+        match self {
+            PlaneKind::Overlay => "0",
+            PlaneKind::Primary => "1",
+            PlaneKind::Cursor => "2",
+        }
+    }
+
+    /// Parses the value of the `type` attribute.
+    pub fn from_attribute(value : &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.to_attribute() == value.trim())
+    }
+}
+
+impl fmt::Display for PlaneKind {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlaneKind::Overlay => write!(f, "overlay"),
+            PlaneKind::Primary => write!(f, "primary"),
+            PlaneKind::Cursor => write!(f, "cursor"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plane {
+    pub name : String,
+    #[serde(rename = "type")]
+    pub kind : PlaneKind,
+    /// Names of the CRTCs linked in `possible_crtcs`.
+    pub possible_crtcs : Vec<String>,
+    /// Fourcc codes of the supported pixel formats. Empty if the kernel doesn't
+    /// report them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formats : Vec<String>,
+    /// Bitmask of the supported DRM rotations and reflections, if the kernel
+    /// reports them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotations : Option<u32>,
+}
+
+impl Plane {
+    fn from_fs(device_path : &Path, name : String) -> io::Result<Self> {
+        let type_path = VkmsDevice::plane_type_path(device_path, &name);
+        let value = fs::read_to_string(type_path)?;
+        let kind = PlaneKind::from_attribute(&value).ok_or_else(|| {
+            invalid_value(format!("plane '{name}': invalid type value '{}'", value.trim()))
+        })?;
+
+        let possible_crtcs = link_names(
+            &VkmsDevice::plane_possible_crtcs_path(device_path, &name),
+            &device_path.join("crtcs"),
+        )?;
+
+        // Read-only attributes only present in newer kernels
+        let formats = read_optional(&VkmsDevice::plane_formats_path(device_path, &name))?
+            .map(|value| value.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        let rotations = read_optional(&VkmsDevice::plane_rotations_path(device_path, &name))?
+            .map(|value| {
+                let value = value.trim();
+                match value.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => value.parse(),
+                }
+                .map_err(|_| {
+                    invalid_value(format!("plane '{name}': invalid rotations value '{value}'"))
+                })
+            })
+            .transpose()?;
+
+        Ok(Plane {
+            name,
+            kind,
+            possible_crtcs,
+            formats,
+            rotations,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crtc {
+    pub name : String,
+    /// Whether the CRTC has a writeback connector.
+    pub writeback : bool,
+}
+
+impl Crtc {
+    fn from_fs(device_path : &Path, name : String) -> io::Result<Self> {
+        // Kernels without writeback support don't have the attribute
+        let writeback_path = VkmsDevice::crtc_writeback_path(device_path, &name);
+        let writeback = match fs::read_to_string(writeback_path) {
+            Ok(value) => value.trim() == "1",
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+
+        Ok(Crtc { name, writeback })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encoder {
+    pub name : String,
+    /// Names of the CRTCs linked in `possible_crtcs`.
+    pub possible_crtcs : Vec<String>,
+}
+
+impl Encoder {
+    fn from_fs(device_path : &Path, name : String) -> io::Result<Self> {
+        let possible_crtcs = link_names(
+            &VkmsDevice::encoder_possible_crtcs_path(device_path, &name),
+            &device_path.join("crtcs"),
+        )?;
+        Ok(Encoder {
+            name,
+            possible_crtcs,
+        })
+    }
+}
+
+/// Status of a connector, as stored in its `status` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectorStatus {
+    Connected,
+    Disconnected,
+    Unknown,
+}
+
+impl ConnectorStatus {
+    pub const ALL : [ConnectorStatus; 3] = [
+        ConnectorStatus::Connected,
+        ConnectorStatus::Disconnected,
+        ConnectorStatus::Unknown,
+    ];
+
+    /// Value of the `status` attribute for this status. These are the values of
+    /// the kernel's `enum drm_connector_status`, which starts at 1.
+    pub fn to_attribute(self) -> &'static str {
+        match self {
+            ConnectorStatus::Connected => "1",
+            ConnectorStatus::Disconnected => "2",
+            ConnectorStatus::Unknown => "3",
+        }
+    }
+
+    /// Parses the value of the `status` attribute.
+    pub fn from_attribute(value : &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.to_attribute() == value.trim())
+    }
+}
+
+impl fmt::Display for ConnectorStatus {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectorStatus::Connected => write!(f, "connected"),
+            ConnectorStatus::Disconnected => write!(f, "disconnected"),
+            ConnectorStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connector {
+    pub name : String,
+    pub status : ConnectorStatus,
+    /// Names of the encoders linked in `possible_encoders`.
+    pub possible_encoders : Vec<String>,
+}
+
+impl Connector {
+    fn from_fs(device_path : &Path, name : String) -> io::Result<Self> {
+        // Without the attribute, connectors are always connected
+        let status_path = VkmsDevice::connector_status_path(device_path, &name);
+        let status = match fs::read_to_string(status_path) {
+            Ok(value) => ConnectorStatus::from_attribute(&value).ok_or_else(|| {
+                let value = value.trim();
+                invalid_value(format!("connector '{name}': invalid status value '{value}'"))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ConnectorStatus::Connected,
+            Err(e) => return Err(e),
+        };
+
+        let possible_encoders = link_names(
+            &VkmsDevice::connector_possible_encoders_path(device_path, &name),
+            &device_path.join("encoders"),
+        )?;
+
+        Ok(Connector {
+            name,
+            status,
+            possible_encoders,
+        })
+    }
+}
+
+/// Likely mistake in a device configuration that doesn't prevent reading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    /// The device has no CRTCs, so it can't be enabled.
+    NoCrtcs,
+    /// The plane has no possible CRTCs.
+    OrphanPlane { plane : String },
+    /// The connector doesn't reach any CRTC through its encoders.
+    StrandedConnector { connector : String },
+    /// The connector has the name of the writeback connector of the CRTC.
+    WritebackNameClash { connector : String, crtc : String },
+    /// The CRTC is reachable by more than one primary or cursor plane.
+    CrowdedCrtc { crtc : String, kind : PlaneKind, planes : Vec<String> },
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationWarning::NoCrtcs => write!(f, "device has no CRTCs; it cannot be enabled"),
+            ValidationWarning::OrphanPlane { plane } => {
+                write!(f, "plane {plane} has no possible CRTCs and can't be used")
+            }
+            ValidationWarning::StrandedConnector { connector } => write!(
+                f,
+                "connector {connector} doesn't reach any CRTC through its encoders"
+            ),
+            ValidationWarning::WritebackNameClash { connector, crtc } => write!(
+                f,
+                "connector {connector} has the name of the writeback connector of CRTC {crtc}"
+            ),
+            ValidationWarning::CrowdedCrtc { crtc, kind, planes } => write!(
+                f,
+                "CRTC {crtc} is reachable by more than one {kind} plane: {}",
+                planes.join(", ")
+            ),
+        }
+    }
+}
+
+/// A VKMS device configured through ConfigFS.
+///
+/// Components are sorted by name when read, so two devices with the same
+/// configuration compare and `Debug` print identically regardless of the order
+/// in which the filesystem lists their directories.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VkmsDevice {
+    pub name : String,
+    pub enabled : bool,
+    pub planes : Vec<Plane>,
+    pub crtcs : Vec<Crtc>,
+    pub encoders : Vec<Encoder>,
+    pub connectors : Vec<Connector>,
+}
+
+impl VkmsDevice {
+    /// Path of the device `name`, `<configfs_path>/<subsystem>/<name>`.
+    ///
+    /// This and the other `*_path` functions only build paths, they don't check
+    /// that they exist.
+    pub fn device_path(configfs_path : &str, subsystem : &str, name : &str) -> PathBuf {
+        Path::new(configfs_path).join(subsystem).join(name)
+    }
+
+    /// Like `device_path`, but returns an `InvalidInput` error if `name` isn't a
+    /// single path component, so the path can't point outside the subsystem.
+    pub fn checked_device_path(
+        configfs_path : &str,
+        subsystem : &str,
+        name : &str,
+    ) -> io::Result<PathBuf> {
+        let components : Vec<_> = Path::new(name).components().collect();
+        if !matches!(components.as_slice(), [Component::Normal(_)]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid device name: {name}"),
+            ));
+        }
+
+        Ok(Self::device_path(configfs_path, subsystem, name))
+    }
+
+    /// Path of the `type` attribute of a plane, given the path of its device.
+    pub fn plane_type_path(device_path : &Path, plane : &str) -> PathBuf {
+        device_path.join("planes").join(plane).join("type")
+    }
+
+    /// Path of the `possible_crtcs` directory of a plane.
+    pub fn plane_possible_crtcs_path(device_path : &Path, plane : &str) -> PathBuf {
+        device_path.join("planes").join(plane).join("possible_crtcs")
+    }
+
+    /// Path of the `supported_formats` attribute of a plane.
+    pub fn plane_formats_path(device_path : &Path, plane : &str) -> PathBuf {
+        device_path.join("planes").join(plane).join("supported_formats")
+    }
+
+    /// Path of the `supported_rotations` attribute of a plane.
+    pub fn plane_rotations_path(device_path : &Path, plane : &str) -> PathBuf {
+        device_path.join("planes").join(plane).join("supported_rotations")
+    }
+
+    /// Path of the `writeback` attribute of a CRTC.
+    pub fn crtc_writeback_path(device_path : &Path, crtc : &str) -> PathBuf {
+        device_path.join("crtcs").join(crtc).join("writeback")
+    }
+
+    /// Path of the `possible_crtcs` directory of an encoder.
+    pub fn encoder_possible_crtcs_path(device_path : &Path, encoder : &str) -> PathBuf {
+        device_path.join("encoders").join(encoder).join("possible_crtcs")
+    }
+
+    /// Path of the `status` attribute of a connector.
+    pub fn connector_status_path(device_path : &Path, connector : &str) -> PathBuf {
+        device_path.join("connectors").join(connector).join("status")
+    }
+
+    /// Path of the `possible_encoders` directory of a connector.
+    pub fn connector_possible_encoders_path(device_path : &Path, connector : &str) -> PathBuf {
+        device_path.join("connectors").join(connector).join("possible_encoders")
+    }
+
+    /// Reads the device `name` from `<configfs_path>/<subsystem>/<name>`.
+    pub fn from_fs(configfs_path : &str, subsystem : &str, name : &str) -> io::Result<Self> {
+        let path = Self::checked_device_path(configfs_path, subsystem, name)?;
+
+        let enabled = Self::is_enabled(configfs_path, subsystem, name)?;
+
+        let planes = dir_names(&path.join("planes"))?
+            .into_iter()
+            .map(|name| Plane::from_fs(&path, name))
+            .collect::<io::Result<_>>()?;
+
+        let crtcs = dir_names(&path.join("crtcs"))?
+            .into_iter()
+            .map(|name| Crtc::from_fs(&path, name))
+            .collect::<io::Result<_>>()?;
+
+        let encoders = dir_names(&path.join("encoders"))?
+            .into_iter()
+            .map(|name| Encoder::from_fs(&path, name))
+            .collect::<io::Result<_>>()?;
+
+        let connectors = dir_names(&path.join("connectors"))?
+            .into_iter()
+            .map(|name| Connector::from_fs(&path, name))
+            .collect::<io::Result<_>>()?;
+
+        Ok(VkmsDevice {
+            name: name.to_string(),
+            enabled,
+            planes,
+            crtcs,
+            encoders,
+            connectors,
+        })
+    }
+
+    /// Reads only the `enabled` attribute of the device `name`. A missing
+    /// attribute means disabled.
+    ///
+    /// Returns a `NotFound` error if the device doesn't exist.
+    pub fn is_enabled(configfs_path : &str, subsystem : &str, name : &str) -> io::Result<bool> {
+        let path = Self::checked_device_path(configfs_path, subsystem, name)?;
+        if !path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Device {name} doesn't exist"),
+            ));
+        }
+
+        // The attribute can be missing while the device is being created or
+        // removed by somebody else
+        match read_optional(&path.join("enabled"))? {
+            Some(value) => Ok(value.trim() == "1"),
+            None => {
+                log::warn!("Device {name} has no enabled attribute, assuming it is disabled");
+                Ok(false)
+            }
+        }
+    }
+
+    /// Names of the planes of the device `name`, read without parsing their
+    /// attributes.
+    pub fn plane_names(
+        configfs_path : &str,
+        subsystem : &str,
+        name : &str,
+    ) -> io::Result<Vec<String>> {
+        let path = Self::checked_device_path(configfs_path, subsystem, name)?;
+        component_names(&path, "planes")
+    }
+
+    /// Names of the CRTCs of the device `name`, see `plane_names`.
+    pub fn crtc_names(
+        configfs_path : &str,
+        subsystem : &str,
+        name : &str,
+    ) -> io::Result<Vec<String>> {
+        let path = Self::checked_device_path(configfs_path, subsystem, name)?;
+        component_names(&path, "crtcs")
+    }
+
+    /// Names of the encoders of the device `name`, see `plane_names`.
+    pub fn encoder_names(
+        configfs_path : &str,
+        subsystem : &str,
+        name : &str,
+    ) -> io::Result<Vec<String>> {
+        let path = Self::checked_device_path(configfs_path, subsystem, name)?;
+        component_names(&path, "encoders")
+    }
+
+    /// Names of the connectors of the device `name`, see `plane_names`.
+    pub fn connector_names(
+        configfs_path : &str,
+        subsystem : &str,
+        name : &str,
+    ) -> io::Result<Vec<String>> {
+        let path = Self::checked_device_path(configfs_path, subsystem, name)?;
+        component_names(&path, "connectors")
+    }
+
+    /// Turns writeback on or off for the CRTC `crtc` of the device `name`,
+    /// disabling the device while writing if it is enabled.
+    ///
+    /// Returns a `NotFound` error if the CRTC doesn't exist.
+    pub fn set_writeback(
+        configfs_path : &str,
+        subsystem : &str,
+        name : &str,
+        crtc : &str,
+        writeback : bool,
+    ) -> io::Result<()> {
+        if !Self::crtc_names(configfs_path, subsystem, name)?.iter().any(|c| c == crtc) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("CRTC {crtc} doesn't exist in device {name}"),
+            ));
+        }
+
+        let attribute = format!("crtcs/{crtc}/writeback");
+        let value = writeback.to_string();
+        crate::attribute::set(configfs_path, subsystem, name, &attribute, &value)?;
+
+        let current = crate::attribute::get(configfs_path, subsystem, name, &attribute)?;
+        if current != value {
+            return Err(io::Error::other(format!(
+                "CRTC {crtc} writeback is {current} after setting it to {value}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every `possible_crtcs` and `possible_encoders` link of the
+    /// device `name` resolves to a CRTC or encoder of the same device.
+    ///
+    /// Returns an `InvalidData` error naming the first link that doesn't.
+    pub fn check_links(configfs_path : &str, subsystem : &str, name : &str) -> io::Result<()> {
+        let device_path = Self::checked_device_path(configfs_path, subsystem, name)?;
+        let groups = [
+            ("planes", "possible_crtcs", "crtcs"),
+            ("encoders", "possible_crtcs", "crtcs"),
+            ("connectors", "possible_encoders", "encoders"),
+        ];
+
+        for (group, links_dir, target_group) in groups {
+            let target_dir = fs::canonicalize(device_path.join(target_group)).ok();
+            for component in component_names(&device_path, group)? {
+                let links_path = device_path.join(group).join(&component).join(links_dir);
+                let links = match fs::read_dir(&links_path) {
+                    Ok(links) => links,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+
+                for link in links {
+                    let link = link?.path();
+                    if !points_into(&link, target_dir.as_deref()) {
+                        let target = fs::read_link(&link)?;
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "{} -> {} doesn't resolve to an item in {target_group} of \
+                                 device {name}",
+                                link.display(),
+                                target.display()
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the device for likely mistakes.
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        let orphan_planes = self.orphan_planes().map(|plane| ValidationWarning::OrphanPlane {
+            plane: plane.name.clone(),
+        });
+        let stranded_connectors = self.stranded_connectors().map(|connector| {
+            ValidationWarning::StrandedConnector {
+                connector: connector.name.clone(),
+            }
+        });
+
+        let no_crtcs = self.crtcs.is_empty().then_some(ValidationWarning::NoCrtcs);
+
+        no_crtcs
+            .into_iter()
+            .chain(orphan_planes)
+            .chain(stranded_connectors)
+            .chain(self.writeback_name_clashes())
+            .collect()
+    }
+
+    /// Connectors named like the connector the kernel adds for a writeback
+    /// CRTC.
+    ///
+    /// This assumes DRM's naming: writeback connectors are called
+    /// `Writeback-<n>`, numbered from 1 in CRTC order.
+    fn writeback_name_clashes(&self) -> impl Iterator<Item = ValidationWarning> + '_ {
+        self.crtcs
+            .iter()
+            .filter(|crtc| crtc.writeback)
+            .enumerate()
+            .flat_map(move |(index, crtc)| {
+                let writeback_name = format!("writeback-{}", index + 1);
+                self.connectors
+                    .iter()
+                    .filter(move |connector| connector.name.to_lowercase() == writeback_name)
+                    .map(|connector| ValidationWarning::WritebackNameClash {
+                        connector: connector.name.clone(),
+                        crtc: crtc.name.clone(),
+                    })
+            })
+    }
+
+    /// Stricter checks than `validate`. Warns about CRTCs reachable by more
+    /// than one primary or cursor plane, which is legal but usually a mistake.
+    pub fn lint(&self) -> Vec<ValidationWarning> {
+        let mut warnings = Vec::new();
+        for crtc in &self.crtcs {
+            for kind in [PlaneKind::Primary, PlaneKind::Cursor] {
+                let planes : Vec<String> = self
+                    .planes
+                    .iter()
+                    .filter(|plane| plane.kind == kind && plane.possible_crtcs.contains(&crtc.name))
+                    .map(|plane| plane.name.clone())
+                    .collect();
+                if planes.len() > 1 {
+                    warnings.push(ValidationWarning::CrowdedCrtc {
+                        crtc: crtc.name.clone(),
+                        kind,
+                        planes,
+                    });
+                }
+            }
+        }
+        warnings
+    }
+
+    /// Planes without possible CRTCs. The kernel creates them, but they can
+    /// never be used.
+    pub fn orphan_planes(&self) -> impl Iterator<Item = &Plane> {
+        self.planes.iter().filter(|plane| plane.possible_crtcs.is_empty())
+    }
+
+    /// Connectors that don't reach any CRTC through their encoders. The kernel
+    /// refuses to enable a device with such a connector.
+    pub fn stranded_connectors(&self) -> impl Iterator<Item = &Connector> {
+        let linked_encoders : Vec<&str> = self
+            .encoders
+            .iter()
+            .filter(|encoder| {
+                encoder
+                    .possible_crtcs
+                    .iter()
+                    .any(|name| self.crtcs.iter().any(|crtc| crtc.name == *name))
+            })
+            .map(|encoder| encoder.name.as_str())
+            .collect();
+
+        self.connectors.iter().filter(move |connector| {
+            !connector
+                .possible_encoders
+                .iter()
+                .any(|name| linked_encoders.contains(&name.as_str()))
+        })
+    }
+}
+
+/// Returns the names of the devices in `<configfs_path>/<subsystem>`, sorted.
+///
+/// The subsystem directory doesn't exist until the module is loaded, so a
+/// missing one means there are no devices. Returns an `Unsupported` error if
+/// `configfs_path` doesn't exist.
+pub fn names(configfs_path : &str, subsystem : &str) -> io::Result<Vec<String>> {
+    if !Path::new(configfs_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{configfs_path} not found, is ConfigFS mounted?"),
+        ));
+    }
+
+    let path = Path::new(configfs_path).join(subsystem);
+    let names = match dir_names(&path) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(names
+        .into_iter()
+        .filter(|name| {
+            let is_dir = path.join(name).is_dir();
+            if !is_dir {
+                log::warn!("Skipping {}: not a device directory", path.join(name).display());
+            }
+            is_dir
+        })
+        .collect())
+}
+
+/// Reads every device in `<configfs_path>/<subsystem>`, sorted by name. See
+/// `names`.
+pub fn list(configfs_path : &str, subsystem : &str) -> io::Result<Vec<VkmsDevice>> {
+    names(configfs_path, subsystem)?
+        .iter()
+        .map(|name| {
+            VkmsDevice::from_fs(configfs_path, subsystem, name)
+                .map_err(|e| with_context(e, format!("Device {name}")))
+        })
+        .collect()
+}
+
+/// A filesystem operation performed by `remove`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoveStep {
+    /// Write 0 to the `enabled` attribute at the path.
+    Disable(PathBuf),
+    /// Remove a `possible_*` link.
+    Unlink(PathBuf),
+    /// Remove a component or device directory.
+    RemoveDir(PathBuf),
+}
+
+impl RemoveStep {
+    fn run(&self) -> io::Result<()> {
+        match self {
+            RemoveStep::Disable(path) => fs::write(path, "0"),
+            RemoveStep::Unlink(path) => fs::remove_file(path),
+            RemoveStep::RemoveDir(path) => fs::remove_dir(path),
+        }
+    }
+}
+
+impl fmt::Display for RemoveStep {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemoveStep::Disable(path) => write!(f, "write 0 to {}", path.display()),
+            RemoveStep::Unlink(path) => write!(f, "unlink {}", path.display()),
+            RemoveStep::RemoveDir(path) => write!(f, "rmdir {}", path.display()),
+        }
+    }
+}
+
+/// Lists the operations `remove` performs for the device `name`, in order,
+/// without changing anything.
+///
+/// The device is disabled first. Then, every `possible_*` link is removed
+/// before removing the components, and the device directory goes last.
+///
+/// Returns a `NotFound` error if the device doesn't exist.
+pub fn remove_steps(
+    configfs_path : &str,
+    subsystem : &str,
+    name : &str,
+) -> io::Result<Vec<RemoveStep>> {
+    let path = VkmsDevice::checked_device_path(configfs_path, subsystem, name)?;
+    if !path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Device {name} doesn't exist"),
+        ));
+    }
+
+    let mut steps = Vec::new();
+
+    // Unlike `VkmsDevice::is_enabled`, don't warn about a missing attribute,
+    // half-created devices are a common reason to remove a device
+    if read_optional(&path.join("enabled"))?.is_some_and(|value| value.trim() == "1") {
+        steps.push(RemoveStep::Disable(path.join("enabled")));
+    }
+
+    let mut components = Vec::new();
+    for group in ["planes", "crtcs", "encoders", "connectors"] {
+        for component in component_names(&path, group)? {
+            components.push(path.join(group).join(component));
+        }
+    }
+
+    for component in &components {
+        for entry in dir_names(component)? {
+            let entry = component.join(entry);
+            if entry.is_dir() && !entry.is_symlink() {
+                for link in dir_names(&entry)? {
+                    steps.push(RemoveStep::Unlink(entry.join(link)));
+                }
+            }
+        }
+    }
+
+    steps.extend(components.into_iter().map(RemoveStep::RemoveDir));
+    steps.push(RemoveStep::RemoveDir(path));
+    Ok(steps)
+}
+
+/// Removes the device `name` without reading its configuration, so devices
+/// that `VkmsDevice::from_fs` can't parse can still be removed. The operations
+/// are the ones listed by `remove_steps`.
+///
+/// Returns a `NotFound` error if the device doesn't exist.
+pub fn remove(configfs_path : &str, subsystem : &str, name : &str) -> io::Result<()> {
+    for step in remove_steps(configfs_path, subsystem, name)? {
+        step.run()?;
+    }
+    Ok(())
+}
+
+/// Waits until the directory of the device `name` is gone, for example after
+/// `remove`, so a new device with the same name can be created safely.
+///
+/// Returns a `TimedOut` error if the device is still present after `timeout`.
+pub fn wait_removed(
+    configfs_path : &str,
+    subsystem : &str,
+    name : &str,
+    timeout : Duration,
+) -> io::Result<()> {
+    let path = VkmsDevice::checked_device_path(configfs_path, subsystem, name)?;
+    let start = Instant::now();
+    while path.exists() {
+        if start.elapsed() >= timeout {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Device {name} is still present after {}ms", timeout.as_millis()),
+            ));
+        }
+        thread::sleep(REMOVE_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Returns the sorted names of the entries in the `path` directory.
+fn dir_names(path : &Path) -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the sorted names of the components in the `group` directory of the
+/// device at `device_path`, or an empty list if the directory doesn't exist.
+fn component_names(device_path : &Path, group : &str) -> io::Result<Vec<String>> {
+    match dir_names(&device_path.join(group)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+/// Error for an attribute whose contents can't be parsed.
+fn invalid_value(message : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads an attribute that older kernels don't have, returning `None` if it
+/// doesn't exist.
+fn read_optional(path : &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `link` resolves to an existing item of `canonical_target_dir`.
+fn points_into(link : &Path, canonical_target_dir : Option<&Path>) -> bool {
+    let resolved = fs::canonicalize(link).ok();
+    let resolved_dir = resolved.as_deref().and_then(Path::parent);
+    resolved_dir.is_some() && resolved_dir == canonical_target_dir
+}
+
+/// Returns the sorted names of the items linked from the `path` directory, or
+/// an empty list if the directory doesn't exist.
+///
+/// Links can be absolute or relative. The name is the last component of the
+/// link target, and a warning is logged if the target doesn't resolve to an
+/// item in `target_dir`.
+fn link_names(path : &Path, target_dir : &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let canonical_target_dir = fs::canonicalize(target_dir).ok();
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let link = entry?.path();
+        let target = fs::read_link(&link)?;
+
+        if !points_into(&link, canonical_target_dir.as_deref()) {
+            log::warn!(
+                "Link {} -> {} doesn't point to an item in {}",
+                link.display(),
+                target.display(),
+                target_dir.display()
+            );
+        }
+
+        let name = target.components().rev().find_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        });
+        if let Some(name) = name {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
@@ -0,0 +1,249 @@
+use crate::device::{ConnectorStatus, PlaneKind, VkmsDevice};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A single difference between two devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Enabled { from : bool, to : bool },
+    PlaneAdded { name : String },
+    PlaneRemoved { name : String },
+    PlaneRetyped { name : String, from : PlaneKind, to : PlaneKind },
+    PlaneRelinked { name : String, from : Vec<String>, to : Vec<String> },
+    CrtcAdded { name : String },
+    CrtcRemoved { name : String },
+    CrtcWriteback { name : String, from : bool, to : bool },
+    EncoderAdded { name : String },
+    EncoderRemoved { name : String },
+    EncoderRelinked { name : String, from : Vec<String>, to : Vec<String> },
+    ConnectorAdded { name : String },
+    ConnectorRemoved { name : String },
+    ConnectorStatusChanged { name : String, from : ConnectorStatus, to : ConnectorStatus },
+    ConnectorRelinked { name : String, from : Vec<String>, to : Vec<String> },
+}
+
+impl Change {
+    /// Short description of the kind of change, used to summarize a diff.
+    fn summary(&self) -> (&'static str, &'static str) {
+        match self {
+            Change::Enabled { .. } => ("enabled flag", "changed"),
+            Change::PlaneAdded { .. } => ("plane", "added"),
+            Change::PlaneRemoved { .. } => ("plane", "removed"),
+            Change::PlaneRetyped { .. } => ("plane", "retyped"),
+            Change::PlaneRelinked { .. } => ("plane", "relinked"),
+            Change::CrtcAdded { .. } => ("CRTC", "added"),
+            Change::CrtcRemoved { .. } => ("CRTC", "removed"),
+            Change::CrtcWriteback { .. } => ("CRTC", "writeback toggled"),
+            Change::EncoderAdded { .. } => ("encoder", "added"),
+            Change::EncoderRemoved { .. } => ("encoder", "removed"),
+            Change::EncoderRelinked { .. } => ("encoder", "relinked"),
+            Change::ConnectorAdded { .. } => ("connector", "added"),
+            Change::ConnectorRemoved { .. } => ("connector", "removed"),
+            Change::ConnectorStatusChanged { .. } => ("connector", "status changed"),
+            Change::ConnectorRelinked { .. } => ("connector", "relinked"),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Enabled { from, to } => write!(f, "enabled: {from} -> {to}"),
+            Change::PlaneAdded { name } => write!(f, "plane {name} added"),
+            Change::PlaneRemoved { name } => write!(f, "plane {name} removed"),
+            Change::PlaneRetyped { name, from, to } => {
+                write!(f, "plane {name} type: {from} -> {to}")
+            }
+            Change::PlaneRelinked { name, from, to } => write!(
+                f,
+                "plane {name} possible_crtcs: [{}] -> [{}]",
+                from.join(", "),
+                to.join(", ")
+            ),
+            Change::CrtcAdded { name } => write!(f, "CRTC {name} added"),
+            Change::CrtcRemoved { name } => write!(f, "CRTC {name} removed"),
+            Change::CrtcWriteback { name, from, to } => {
+                write!(f, "CRTC {name} writeback: {from} -> {to}")
+            }
+            Change::EncoderAdded { name } => write!(f, "encoder {name} added"),
+            Change::EncoderRemoved { name } => write!(f, "encoder {name} removed"),
+            Change::EncoderRelinked { name, from, to } => write!(
+                f,
+                "encoder {name} possible_crtcs: [{}] -> [{}]",
+                from.join(", "),
+                to.join(", ")
+            ),
+            Change::ConnectorAdded { name } => write!(f, "connector {name} added"),
+            Change::ConnectorRemoved { name } => write!(f, "connector {name} removed"),
+            Change::ConnectorStatusChanged { name, from, to } => {
+                write!(f, "connector {name} status: {from} -> {to}")
+            }
+            Change::ConnectorRelinked { name, from, to } => write!(
+                f,
+                "connector {name} possible_encoders: [{}] -> [{}]",
+                from.join(", "),
+                to.join(", ")
+            ),
+        }
+    }
+}
+
+/// Differences between two devices, as returned by `VkmsDevice::diff`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeviceDiff {
+    pub changes : Vec<Change>,
+}
+
+impl DeviceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Summarizes the diff, for example "2 planes added, 1 CRTC writeback toggled".
+impl fmt::Display for DeviceDiff {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let mut counts : Vec<((&str, &str), usize)> = Vec::new();
+        for change in &self.changes {
+            let summary = change.summary();
+            match counts.iter_mut().find(|(s, _)| *s == summary) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((summary, 1)),
+            }
+        }
+
+        let parts : Vec<_> = counts
+            .into_iter()
+            .map(|((noun, what), count)| {
+                let plural = if count == 1 { "" } else { "s" };
+                format!("{count} {noun}{plural} {what}")
+            })
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl VkmsDevice {
+    /// Computes the changes needed to turn `self` into `other`. Device names
+    /// are not compared.
+    pub fn diff(&self, other : &Self) -> DeviceDiff {
+        let mut changes = Vec::new();
+
+        if self.enabled != other.enabled {
+            changes.push(Change::Enabled {
+                from: self.enabled,
+                to: other.enabled,
+            });
+        }
+
+        let planes : BTreeMap<_, _> = self.planes.iter().map(|p| (&p.name, p)).collect();
+        let other_planes : BTreeMap<_, _> = other.planes.iter().map(|p| (&p.name, p)).collect();
+
+        for (name, plane) in &planes {
+            let Some(other_plane) = other_planes.get(name) else {
+                changes.push(Change::PlaneRemoved { name: name.to_string() });
+                continue;
+            };
+
+            if plane.kind != other_plane.kind {
+                changes.push(Change::PlaneRetyped {
+                    name: name.to_string(),
+                    from: plane.kind,
+                    to: other_plane.kind,
+                });
+            }
+
+            if plane.possible_crtcs != other_plane.possible_crtcs {
+                changes.push(Change::PlaneRelinked {
+                    name: name.to_string(),
+                    from: plane.possible_crtcs.clone(),
+                    to: other_plane.possible_crtcs.clone(),
+                });
+            }
+        }
+
+        for name in other_planes.keys().filter(|name| !planes.contains_key(*name)) {
+            changes.push(Change::PlaneAdded { name: name.to_string() });
+        }
+
+        let crtcs : BTreeMap<_, _> = self.crtcs.iter().map(|c| (&c.name, c)).collect();
+        let other_crtcs : BTreeMap<_, _> = other.crtcs.iter().map(|c| (&c.name, c)).collect();
+
+        for (name, crtc) in &crtcs {
+            match other_crtcs.get(name) {
+                None => changes.push(Change::CrtcRemoved { name: name.to_string() }),
+                Some(other_crtc) if crtc.writeback != other_crtc.writeback => {
+                    changes.push(Change::CrtcWriteback {
+                        name: name.to_string(),
+                        from: crtc.writeback,
+                        to: other_crtc.writeback,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        for name in other_crtcs.keys().filter(|name| !crtcs.contains_key(*name)) {
+            changes.push(Change::CrtcAdded { name: name.to_string() });
+        }
+
+        let encoders : BTreeMap<_, _> = self.encoders.iter().map(|e| (&e.name, e)).collect();
+        let other_encoders : BTreeMap<_, _> =
+            other.encoders.iter().map(|e| (&e.name, e)).collect();
+
+        for (name, encoder) in &encoders {
+            match other_encoders.get(name) {
+                None => changes.push(Change::EncoderRemoved { name: name.to_string() }),
+                Some(other_encoder) if encoder.possible_crtcs != other_encoder.possible_crtcs => {
+                    changes.push(Change::EncoderRelinked {
+                        name: name.to_string(),
+                        from: encoder.possible_crtcs.clone(),
+                        to: other_encoder.possible_crtcs.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        for name in other_encoders.keys().filter(|name| !encoders.contains_key(*name)) {
+            changes.push(Change::EncoderAdded { name: name.to_string() });
+        }
+
+        let connectors : BTreeMap<_, _> = self.connectors.iter().map(|c| (&c.name, c)).collect();
+        let other_connectors : BTreeMap<_, _> =
+            other.connectors.iter().map(|c| (&c.name, c)).collect();
+
+        for (name, connector) in &connectors {
+            let Some(other_connector) = other_connectors.get(name) else {
+                changes.push(Change::ConnectorRemoved { name: name.to_string() });
+                continue;
+            };
+
+            if connector.status != other_connector.status {
+                changes.push(Change::ConnectorStatusChanged {
+                    name: name.to_string(),
+                    from: connector.status,
+                    to: other_connector.status,
+                });
+            }
+
+            if connector.possible_encoders != other_connector.possible_encoders {
+                changes.push(Change::ConnectorRelinked {
+                    name: name.to_string(),
+                    from: connector.possible_encoders.clone(),
+                    to: other_connector.possible_encoders.clone(),
+                });
+            }
+        }
+
+        for name in other_connectors.keys().filter(|name| !connectors.contains_key(*name)) {
+            changes.push(Change::ConnectorAdded { name: name.to_string() });
+        }
+
+        DeviceDiff { changes }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::device::{ConnectorStatus, VkmsDevice};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Directory where the kernel lists DRM cards and connectors.
+pub const DRM_SYSFS_PATH : &str = "/sys/class/drm";
+
+/// Prefix of the DRM connectors of writeback CRTCs, which have no ConfigFS
+/// connector.
+const WRITEBACK_PREFIX : &str = "Writeback-";
+
+/// A DRM connector created by the kernel for a ConfigFS connector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrmConnector {
+    /// Name of the connector in ConfigFS.
+    pub configfs_name : String,
+    /// Name of the connector in DRM, for example Virtual-1.
+    pub name : String,
+    /// DRM object ID, if the kernel exposes it.
+    pub id : Option<u32>,
+    pub status : ConnectorStatus,
+}
+
+impl VkmsDevice {
+    /// Matches the connectors of an enabled device with the DRM connectors in
+    /// `drm_path`, usually `DRM_SYSFS_PATH`.
+    ///
+    /// The kernel creates one DRM connector per ConfigFS connector, so they are
+    /// matched by position, assuming the ConfigFS connectors were created in
+    /// name order. DRM connectors are ordered by ID or, if the kernel doesn't
+    /// expose it, by the number in their name. The `Writeback-<n>` connectors
+    /// the kernel adds for writeback CRTCs are skipped. Returns an empty vector
+    /// and logs a warning if the card or its connectors can't be found.
+    pub fn drm_connectors(&self, drm_path : &str) -> Vec<DrmConnector> {
+        match self.read_drm_connectors(Path::new(drm_path)) {
+            Ok(connectors) => connectors,
+            Err(e) => {
+                log::warn!("Device {}: can't map DRM connectors: {e}", self.name);
+                Vec::new()
+            }
+        }
+    }
+
+    /// DRM object ID of the connector called `connector` in ConfigFS, see
+    /// `drm_connectors`. Returns `None` if the connector can't be matched or
+    /// the kernel doesn't expose its ID.
+    ///
+    /// Planes and CRTCs don't have a sysfs entry, so their IDs can only be
+    /// found through the DRM ioctls.
+    pub fn drm_connector_id(&self, drm_path : &str, connector : &str) -> Option<u32> {
+        self.drm_connectors(drm_path)
+            .into_iter()
+            .find(|drm_connector| drm_connector.configfs_name == connector)
+            .and_then(|drm_connector| drm_connector.id)
+    }
+
+    fn read_drm_connectors(&self, drm_path : &Path) -> io::Result<Vec<DrmConnector>> {
+        let card = find_card(drm_path, &self.name)?;
+        let prefix = format!("{card}-");
+
+        let mut drm_connectors = Vec::new();
+        for entry in fs::read_dir(drm_path)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let Some(connector_name) = name.strip_prefix(&prefix) else {
+                continue;
+            };
+            if connector_name.starts_with(WRITEBACK_PREFIX) {
+                continue;
+            }
+
+            let path = drm_path.join(&name);
+            let status = fs::read_to_string(path.join("status"))?;
+            let status = ConnectorStatus::ALL
+                .into_iter()
+                .find(|s| s.to_string() == status.trim())
+                .unwrap_or(ConnectorStatus::Unknown);
+            let id = fs::read_to_string(path.join("connector_id"))
+                .ok()
+                .and_then(|id| id.trim().parse().ok());
+            drm_connectors.push((connector_name.to_string(), id, status));
+        }
+        drm_connectors.sort_by_key(|(name, id, _)| (*id, name_number(name), name.clone()));
+
+        if drm_connectors.len() != self.connectors.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{card} has {} connectors, expected {}",
+                    drm_connectors.len(),
+                    self.connectors.len()
+                ),
+            ));
+        }
+
+        Ok(self
+            .connectors
+            .iter()
+            .zip(drm_connectors)
+            .map(|(connector, (name, id, status))| DrmConnector {
+                configfs_name: connector.name.clone(),
+                name,
+                id,
+                status,
+            })
+            .collect())
+    }
+}
+
+/// Number at the end of a DRM connector name, for example 10 for Virtual-10.
+fn name_number(name : &str) -> Option<u32> {
+    name.rsplit_once('-').and_then(|(_, number)| number.parse().ok())
+}
+
+/// Returns the name of the card, for example card1, whose platform device is
+/// called `device_name`.
+fn find_card(drm_path : &Path, device_name : &str) -> io::Result<String> {
+    for entry in fs::read_dir(drm_path)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let is_card = name
+            .strip_prefix("card")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+        if !is_card {
+            continue;
+        }
+
+        let device = fs::read_link(drm_path.join(&name).join("device")).unwrap_or_default();
+        if device.file_name().is_some_and(|n| n == device_name) {
+            return Ok(name);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no DRM card for {device_name} in {}", drm_path.display()),
+    ))
+}
@@ -0,0 +1,36 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Message describing what failed, keeping the original error as its source.
+///
+/// It is displayed followed by the source, "message: source", so printing the
+/// error with `{}` shows the cause. The alternate form, `{:#}`, shows only the
+/// message, for callers that print each `Error::source` themselves.
+#[derive(Debug)]
+struct Context {
+    message : String,
+    source : io::Error,
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.message, self.source)
+        }
+    }
+}
+
+impl Error for Context {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Wraps `error` in an error of the same kind displayed as "message: error".
+/// The original error is also available through `Error::source`.
+pub(crate) fn with_context(error : io::Error, message : String) -> io::Error {
+    io::Error::new(error.kind(), Context { message, source: error })
+}
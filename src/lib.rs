@@ -0,0 +1,6 @@
+pub mod attribute;
+pub mod capabilities;
+pub mod device;
+pub mod diff;
+pub mod drm;
+mod error;
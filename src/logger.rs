@@ -0,0 +1,54 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Logger printing messages to stderr.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata : &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record : &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let prefix = match record.level() {
+            Level::Error => "Error",
+            Level::Warn => "Warning",
+            Level::Info => "Info",
+            Level::Debug => "Debug",
+            Level::Trace => "Trace",
+        };
+        eprintln!("{prefix}: {}", record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER : StderrLogger = StderrLogger;
+
+/// Picks the maximum log level. From highest to lowest precedence: `level`
+/// (--log-level), the `RUST_LOG` environment variable, `verbosity` (one -v
+/// per level above the default, -1 for -q) and the default, `Info`.
+fn max_level(level : Option<LevelFilter>, verbosity : i8) -> LevelFilter {
+    let rust_log = std::env::var("RUST_LOG").ok().and_then(|value| value.parse().ok());
+    level.or(rust_log).unwrap_or(match verbosity {
+        ..=-1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    })
+}
+
+/// Installs the logger, see `max_level` for the arguments. Safe to call more
+/// than once: returns `false`, leaving the installed logger untouched, if a
+/// logger was already installed by a previous call or by somebody else.
+pub fn init(level : Option<LevelFilter>, verbosity : i8) -> bool {
+    if log::set_logger(&LOGGER).is_err() {
+        return false;
+    }
+
+    log::set_max_level(max_level(level, verbosity));
+    true
+}
@@ -1,18 +1,543 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use vkmsctl::device::{
+    Connector, ConnectorStatus, Crtc, Encoder, Plane, PlaneKind, ValidationWarning, VkmsDevice,
+};
+use vkmsctl::{attribute, capabilities, device};
+
 mod args_parser;
+mod logger;
+
+/// Whether `component` is printed given the `--only` list, empty for all.
+fn is_shown(only : &[args_parser::Component], component : args_parser::Component) -> bool {
+    only.is_empty() || only.contains(&component)
+}
+
+fn display_device(
+    out : &mut dyn Write,
+    device : &VkmsDevice,
+    only : &[args_parser::Component],
+) -> io::Result<()> {
+    let shown = |component| is_shown(only, component);
+    let status = if device.enabled { "enabled" } else { "disabled" };
+    writeln!(out, "{} ({status})", device.name)?;
+
+    if shown(args_parser::Component::Planes) {
+        writeln!(out, "  Planes:")?;
+        for plane in &device.planes {
+            writeln!(
+                out,
+                "    {} ({}) -> {}",
+                plane.name,
+                plane.kind,
+                plane.possible_crtcs.join(", ")
+            )?;
+            if !plane.formats.is_empty() {
+                writeln!(out, "      Formats: {}", plane.formats.join(", "))?;
+            }
+            if let Some(rotations) = plane.rotations {
+                writeln!(out, "      Rotations: {rotations:#x}")?;
+            }
+        }
+    }
+
+    if shown(args_parser::Component::Crtcs) {
+        writeln!(out, "  CRTCs:")?;
+        for crtc in &device.crtcs {
+            if crtc.writeback {
+                writeln!(out, "    {} (writeback)", crtc.name)?;
+            } else {
+                writeln!(out, "    {}", crtc.name)?;
+            }
+        }
+    }
+
+    if shown(args_parser::Component::Encoders) {
+        writeln!(out, "  Encoders:")?;
+        for encoder in &device.encoders {
+            writeln!(out, "    {} -> {}", encoder.name, encoder.possible_crtcs.join(", "))?;
+        }
+    }
+
+    if shown(args_parser::Component::Connectors) {
+        writeln!(out, "  Connectors:")?;
+        for connector in &device.connectors {
+            writeln!(
+                out,
+                "    {} ({}) -> {}",
+                connector.name,
+                connector.status,
+                connector.possible_encoders.join(", ")
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn display_summary(out : &mut dyn Write, devices : &[VkmsDevice]) -> io::Result<()> {
+    let enabled = devices.iter().filter(|device| device.enabled).count();
+    writeln!(out, "Devices: {} ({enabled} enabled)", devices.len())?;
+
+    let planes : Vec<_> = devices.iter().flat_map(|device| &device.planes).collect();
+    let plane_kinds : Vec<_> = PlaneKind::ALL
+        .iter()
+        .map(|kind| {
+            let count = planes.iter().filter(|plane| plane.kind == *kind).count();
+            format!("{count} {kind}")
+        })
+        .collect();
+    writeln!(out, "Planes: {} ({})", planes.len(), plane_kinds.join(", "))?;
+
+    let crtcs : Vec<_> = devices.iter().flat_map(|device| &device.crtcs).collect();
+    let writeback = crtcs.iter().filter(|crtc| crtc.writeback).count();
+    writeln!(out, "CRTCs: {} ({writeback} writeback)", crtcs.len())?;
+
+    let encoders = devices.iter().map(|device| device.encoders.len()).sum::<usize>();
+    writeln!(out, "Encoders: {encoders}")?;
+
+    let connectors : Vec<_> = devices.iter().flat_map(|device| &device.connectors).collect();
+    let connector_statuses : Vec<_> = ConnectorStatus::ALL
+        .iter()
+        .map(|status| {
+            let count = connectors.iter().filter(|c| c.status == *status).count();
+            format!("{count} {status}")
+        })
+        .collect();
+    writeln!(out, "Connectors: {} ({})", connectors.len(), connector_statuses.join(", "))?;
+
+    Ok(())
+}
+
+/// Reads every device. With `keep_going`, devices that can't be read are
+/// logged and counted instead of failing.
+fn read_devices(
+    configfs_path : &str,
+    subsystem : &str,
+    keep_going : bool,
+) -> io::Result<(Vec<VkmsDevice>, usize)> {
+    if !keep_going {
+        return Ok((device::list(configfs_path, subsystem)?, 0));
+    }
+
+    let mut devices = Vec::new();
+    let mut failure_count = 0;
+    for name in device::names(configfs_path, subsystem)? {
+        match VkmsDevice::from_fs(configfs_path, subsystem, &name) {
+            Ok(device) => devices.push(device),
+            Err(error) => {
+                log::error!("Device {name}: {error}");
+                failure_count += 1;
+            }
+        }
+    }
+    Ok((devices, failure_count))
+}
+
+/// Errors if some devices couldn't be read with --keep-going.
+fn check_failures(failure_count : usize) -> io::Result<()> {
+    if failure_count > 0 {
+        return Err(io::Error::other(format!("{failure_count} device(s) could not be read")));
+    }
+    Ok(())
+}
+
+fn display_current_config(
+    configfs_path : &str,
+    subsystem : &str,
+    config_args : &args_parser::ConfigArgs,
+) -> io::Result<()> {
+    let (devices, failure_count) =
+        read_devices(configfs_path, subsystem, config_args.batch.keep_going)?;
+
+    let mut warning_count = 0;
+    for device in &devices {
+        let mut warnings = device.validate();
+        if config_args.lint {
+            warnings.extend(device.lint());
+        }
+        let warnings = warnings.into_iter().filter(|warning| match warning {
+            ValidationWarning::NoCrtcs => !config_args.allow_no_crtcs,
+            ValidationWarning::OrphanPlane { .. } => !config_args.allow_orphan_planes,
+            _ => true,
+        });
+        for warning in warnings {
+            log::warn!("Device {}: {warning}", device.name);
+            warning_count += 1;
+        }
+    }
+
+    let mut out = output_writer(&config_args.output)?;
+    if let Some(format) = config_args.format {
+        write_devices(&mut out, &devices, &config_args.only, format)?;
+    } else if config_args.summary {
+        display_summary(&mut out, &devices)?;
+    } else if devices.is_empty() {
+        writeln!(out, "No VKMS devices configured")?;
+    } else {
+        for device in &devices {
+            display_device(&mut out, device, &config_args.only)?;
+        }
+    }
+    out.flush()?;
+
+    check_failures(failure_count)?;
+
+    if config_args.deny_warnings && warning_count > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{warning_count} warning(s) found and --deny-warnings is set"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Document written by the machine-readable formats.
+#[derive(Serialize)]
+struct Devices<'a> {
+    devices : Vec<DeviceView<'a>>,
+}
+
+/// A device as written by the machine-readable formats. Components left out
+/// with `--only` are omitted rather than written as empty lists.
+#[derive(Serialize)]
+struct DeviceView<'a> {
+    name : &'a str,
+    enabled : bool,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_shown")]
+    planes : Option<&'a [Plane]>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_shown")]
+    crtcs : Option<&'a [Crtc]>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_shown")]
+    encoders : Option<&'a [Encoder]>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_shown")]
+    connectors : Option<&'a [Connector]>,
+}
+
+/// Writes a shown component list as is, RON would wrap it in `Some(...)`.
+fn serialize_shown<S : serde::Serializer, T : Serialize>(
+    components : &Option<&[T]>,
+    serializer : S,
+) -> Result<S::Ok, S::Error> {
+    components.unwrap_or_default().serialize(serializer)
+}
+
+impl<'a> DeviceView<'a> {
+    fn new(device : &'a VkmsDevice, only : &[args_parser::Component]) -> Self {
+        use args_parser::Component;
+        let shown = |component| is_shown(only, component);
+        DeviceView {
+            name: &device.name,
+            enabled: device.enabled,
+            planes: shown(Component::Planes).then_some(&device.planes[..]),
+            crtcs: shown(Component::Crtcs).then_some(&device.crtcs[..]),
+            encoders: shown(Component::Encoders).then_some(&device.encoders[..]),
+            connectors: shown(Component::Connectors).then_some(&device.connectors[..]),
+        }
+    }
+}
+
+fn write_devices(
+    out : &mut dyn Write,
+    devices : &[VkmsDevice],
+    only : &[args_parser::Component],
+    format : args_parser::DataFormat,
+) -> io::Result<()> {
+    let devices = Devices {
+        devices: devices.iter().map(|device| DeviceView::new(device, only)).collect(),
+    };
+    match format {
+        args_parser::DataFormat::Json => serde_json::to_writer_pretty(&mut *out, &devices)?,
+        args_parser::DataFormat::Ron => {
+            let ron = ron::ser::to_string_pretty(&devices, ron::ser::PrettyConfig::default())
+                .map_err(io::Error::other)?;
+            out.write_all(ron.as_bytes())?;
+        }
+    }
+    writeln!(out)
+}
+
+fn dump_all(
+    configfs_path : &str,
+    subsystem : &str,
+    format : args_parser::DataFormat,
+    batch : &args_parser::BatchArgs,
+    output : &args_parser::OutputArgs,
+) -> io::Result<()> {
+    let (devices, failure_count) = read_devices(configfs_path, subsystem, !batch.fail_fast)?;
+
+    let mut out = output_writer(output)?;
+    write_devices(&mut out, &devices, &[], format)?;
+    out.flush()?;
+
+    check_failures(failure_count)
+}
+
+/// Reads the device `name` from a JSON file with a single device or with the
+/// list of devices written by dump-all.
+fn read_device_config(path : &Path, name : &str) -> io::Result<VkmsDevice> {
+    let reader = io::BufReader::new(File::open(path)?);
+    let config : serde_json::Value = serde_json::from_reader(reader)?;
+
+    let device = match config.get("devices").and_then(|devices| devices.as_array()) {
+        Some(devices) => devices.iter().find(|device| device["name"] == name).cloned(),
+        None => Some(config),
+    };
+    let device = device.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Device {name} not found in {}", path.display()),
+        )
+    })?;
+
+    Ok(serde_json::from_value(device)?)
+}
+
+/// Prints how the device `name` differs from its description in `config`.
+fn display_drift(
+    configfs_path : &str,
+    subsystem : &str,
+    name : &str,
+    config : &Path,
+) -> io::Result<()> {
+    let expected = read_device_config(config, name)?;
+    let live = VkmsDevice::from_fs(configfs_path, subsystem, name)?;
+
+    let diff = expected.diff(&live);
+    if diff.is_empty() {
+        println!("No drift");
+        return Ok(());
+    }
+
+    for change in &diff.changes {
+        println!("{change}");
+    }
+    Err(io::Error::other(format!(
+        "Device {name} drifted from {}: {diff}",
+        config.display()
+    )))
+}
+
+fn display_capabilities(
+    configfs_path : &str,
+    subsystem : &str,
+    output : &args_parser::OutputArgs,
+) -> io::Result<()> {
+    let yes_no = |supported| if supported { "yes" } else { "no" };
+    let capabilities = capabilities::probe(configfs_path, subsystem)?;
+
+    let mut out = output_writer(output)?;
+
+    writeln!(out, "ConfigFS devices: {}", yes_no(capabilities.configfs))?;
+    writeln!(out, "Writeback: {}", yes_no(capabilities.writeback))?;
+    writeln!(out, "Connector status: {}", yes_no(capabilities.connector_status))?;
 
-fn display_current_config(_configfs_path : &str) {
+    let plane_types : Vec<_> =
+        capabilities.plane_types.iter().map(|kind| kind.to_string()).collect();
+    writeln!(out, "Plane types: {}", plane_types.join(", "))?;
 
+    out.flush()
+}
+
+/// Returns the file given with `--output`, or stdout if there is none.
+fn output_writer(output : &args_parser::OutputArgs) -> io::Result<Box<dyn Write>> {
+    match &output.output {
+        Some(path) => Ok(Box::new(io::BufWriter::new(File::create(path)?))),
+        None => Ok(Box::new(io::stdout().lock())),
+    }
+}
+
+/// Maps an error to the process exit code documented in the README.
+fn exit_code(error : &io::Error) -> i32 {
+    match error.kind() {
+        io::ErrorKind::InvalidInput => 2,
+        io::ErrorKind::AlreadyExists => 3,
+        io::ErrorKind::NotFound => 4,
+        io::ErrorKind::PermissionDenied => 5,
+        io::ErrorKind::Unsupported => 6,
+        _ if is_out_of_resources(error) => 7,
+        io::ErrorKind::TimedOut => 8,
+        _ => 1,
+    }
+}
+
+/// Whether the kernel ran out of memory or space (ENOMEM, ENOSPC), which is
+/// not caused by the configuration itself.
+fn is_out_of_resources(error : &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::OutOfMemory | io::ErrorKind::StorageFull)
+}
+
+/// Checks that the ConfigFS path given on the command line is a directory and,
+/// unless `allow_relative` is set, an absolute path. A missing path means
+/// ConfigFS isn't available and returns an `Unsupported` error.
+fn check_configfs_path(configfs_path : &str, allow_relative : bool) -> io::Result<()> {
+    if !allow_relative && Path::new(configfs_path).is_relative() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "configfs path must be absolute: {configfs_path} \
+                 (use --allow-relative-configfs to accept it)"
+            ),
+        ));
+    }
+
+    let path = Path::new(configfs_path);
+    if !path.exists() {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{configfs_path} not found, is ConfigFS mounted?"),
+        ))
+    } else if path.is_dir() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("configfs path is not a directory: {configfs_path}"),
+        ))
+    }
+}
+
+fn run(args : &args_parser::Args) -> io::Result<()> {
+    if args.command.is_some() {
+        check_configfs_path(&args.configfs_path, args.allow_relative_configfs)?;
+    }
+
+    match &args.command {
+        Some(args_parser::Commands::Config(config_args)) => {
+            display_current_config(&args.configfs_path, &args.subsystem, config_args)
+        }
+        Some(args_parser::Commands::Get {
+            device,
+            attribute,
+            output,
+        }) => {
+            let value = attribute::get(&args.configfs_path, &args.subsystem, device, attribute)?;
+            let mut out = output_writer(output)?;
+            writeln!(out, "{value}")?;
+            out.flush()
+        }
+        Some(args_parser::Commands::Set {
+            device,
+            attribute,
+            value,
+            enable_retries,
+        }) => attribute::set_with_retries(
+            &args.configfs_path,
+            &args.subsystem,
+            device,
+            attribute,
+            value,
+            *enable_retries,
+        ),
+        Some(args_parser::Commands::Capabilities { output }) => {
+            display_capabilities(&args.configfs_path, &args.subsystem, output)
+        }
+        Some(args_parser::Commands::DumpAll {
+            format,
+            batch,
+            output,
+        }) => dump_all(&args.configfs_path, &args.subsystem, *format, batch, output),
+        Some(args_parser::Commands::Diff { name, config }) => {
+            display_drift(&args.configfs_path, &args.subsystem, name, config)
+        }
+        Some(args_parser::Commands::Remove {
+            name,
+            dry_run: true,
+            ..
+        }) => {
+            for step in device::remove_steps(&args.configfs_path, &args.subsystem, name)? {
+                println!("{step}");
+            }
+            Ok(())
+        }
+        Some(args_parser::Commands::Remove {
+            name,
+            wait,
+            wait_timeout,
+            ..
+        }) => {
+            let start = Instant::now();
+            device::remove(&args.configfs_path, &args.subsystem, name)?;
+            if *wait {
+                let timeout = Duration::from_secs(*wait_timeout);
+                device::wait_removed(&args.configfs_path, &args.subsystem, name, timeout)?;
+            }
+            log::info!("Removed device {name} in {}ms", start.elapsed().as_millis());
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Prints `error`, which includes its sources, or, with `verbose`, one cause
+/// per line.
+fn display_error(error : &io::Error, verbose : bool) {
+    if !verbose {
+        eprintln!("Error: {error}");
+        return;
+    }
+
+    // The alternate form of errors with context leaves out their source
+    eprintln!("Error: {error:#}");
+    let mut causes = Vec::new();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        causes.push(format!("{cause:#}"));
+        source = cause.source();
+    }
+    if !causes.is_empty() {
+        eprintln!("Caused by:");
+        for (index, cause) in causes.iter().enumerate() {
+            eprintln!("  {index}: {cause}");
+        }
+    }
+}
+
+/// Runs the command on a worker thread and gives up after `timeout`. Blocking
+/// filesystem calls can't be interrupted, so a hung worker is left behind and
+/// dies when the process exits.
+fn run_with_timeout(args : args_parser::Args, timeout : Duration) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || sender.send(run(&args)));
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("command timed out after {}s", timeout.as_secs()),
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(io::Error::other("command failed unexpectedly"))
+        }
+    }
 }
 
 fn main() {
     let args = args_parser::parse();
 
-    println!("Command line args: {:?}", args);
+    let verbosity = if args.quiet { -1 } else { args.verbose.min(2) as i8 };
+    logger::init(args.log_level.map(Into::into), verbosity);
 
-    match &args.command {
-        Some(args_parser::Commands::Config {}) => {
-            display_current_config(&args.configfs_path);
+    log::debug!("Command line args: {:?}", args);
+
+    let verbose_errors = args.verbose_errors || args.verbose >= 2;
+    let result = match args.timeout {
+        Some(timeout) => run_with_timeout(args, Duration::from_secs(timeout)),
+        None => run(&args),
+    };
+
+    if let Err(error) = result {
+        display_error(&error, verbose_errors);
+        if is_out_of_resources(&error) {
+            eprintln!("The system is out of memory or space; this is not a configuration problem");
         }
-        None => {}
+        process::exit(exit_code(&error));
     }
 }
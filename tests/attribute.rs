@@ -0,0 +1,276 @@
+mod common;
+
+use std::fs;
+use std::io::ErrorKind;
+use vkmsctl::attribute;
+use vkmsctl::device::{VkmsDevice, VKMS_SUBSYSTEM};
+
+const CONFIGFS_MOCK : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/config-mock");
+
+fn get(attribute : &str) -> std::io::Result<String> {
+    attribute::get(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "device1", attribute)
+}
+
+#[test]
+fn get_decodes_known_attributes() {
+    assert_eq!(get("enabled").unwrap(), "true");
+    assert_eq!(get("planes/cursor/type").unwrap(), "cursor");
+    assert_eq!(get("planes/primary/type").unwrap(), "primary");
+}
+
+#[test]
+fn get_decodes_connector_status_and_raw_values() {
+    let configfs = tempfile::tempdir().unwrap();
+    let device_path = configfs.path().join("vkms/device");
+    fs::create_dir_all(device_path.join("connectors/connector0")).unwrap();
+    fs::write(device_path.join("connectors/connector0/status"), "2\n").unwrap();
+    fs::write(device_path.join("connectors/connector0/other"), "raw value\n").unwrap();
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let get = |attribute| {
+        attribute::get(configfs_path, VKMS_SUBSYSTEM, "device", attribute).unwrap()
+    };
+
+    assert_eq!(get("connectors/connector0/status"), "disconnected");
+    assert_eq!(get("connectors/connector0/other"), "raw value");
+}
+
+#[test]
+fn get_rejects_paths_outside_the_device() {
+    for path in [
+        "",
+        "../device1/enabled",
+        "planes/../../device1/enabled",
+        "/etc/passwd",
+        "./enabled",
+    ] {
+        assert_eq!(get(path).unwrap_err().kind(), ErrorKind::InvalidInput, "{path}");
+    }
+}
+
+#[test]
+fn get_rejects_devices_outside_the_subsystem() {
+    let dir = tempfile::tempdir().unwrap();
+    let configfs_path = dir.path().join("configfs");
+    fs::create_dir_all(configfs_path.join("vkms")).unwrap();
+    fs::create_dir(dir.path().join("outside")).unwrap();
+    fs::write(dir.path().join("outside/victim"), "secret").unwrap();
+    let configfs_path = configfs_path.to_str().unwrap();
+
+    for device in ["../../outside", "..", "/", ""] {
+        let err = attribute::get(configfs_path, VKMS_SUBSYSTEM, device, "victim").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{device}");
+    }
+}
+
+#[test]
+fn get_fails_for_missing_devices() {
+    let err = attribute::get(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "missing", "enabled").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+/// Creates a fake ConfigFS with an enabled device with a CRTC and a connector.
+fn enabled_configfs() -> tempfile::TempDir {
+    let configfs = common::fake_configfs(&[], &["crtc0"]);
+    let device_path = configfs.path().join("vkms/device");
+    fs::create_dir_all(device_path.join("connectors/connector0")).unwrap();
+    fs::write(device_path.join("enabled"), "1").unwrap();
+    fs::write(device_path.join("crtcs/crtc0/writeback"), "0").unwrap();
+    fs::write(device_path.join("connectors/connector0/status"), "1").unwrap();
+    configfs
+}
+
+#[test]
+fn set_encodes_known_attributes() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+    let device_path = configfs.path().join("vkms/device");
+    let set = |attribute, value| {
+        attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", attribute, value)
+    };
+    let raw = |attribute| fs::read_to_string(device_path.join(attribute)).unwrap();
+
+    set("crtcs/crtc0/writeback", "true").unwrap();
+    assert_eq!(raw("crtcs/crtc0/writeback"), "1");
+    assert_eq!(raw("enabled"), "1");
+
+    set("connectors/connector0/status", "disconnected").unwrap();
+    assert_eq!(raw("connectors/connector0/status"), "2");
+
+    set("connectors/connector0/status", "3").unwrap();
+    assert_eq!(raw("connectors/connector0/status"), "3");
+
+    let err = set("crtcs/crtc0/writeback", "maybe").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(raw("crtcs/crtc0/writeback"), "1");
+}
+
+#[test]
+fn set_rejects_paths_outside_the_device() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let err =
+        attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", "../escape", "1").unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(!configfs.path().join("vkms/escape").exists());
+}
+
+#[test]
+fn set_rejects_devices_outside_the_subsystem() {
+    let dir = tempfile::tempdir().unwrap();
+    let configfs_path = dir.path().join("configfs");
+    fs::create_dir_all(configfs_path.join("vkms")).unwrap();
+    fs::create_dir(dir.path().join("outside")).unwrap();
+    fs::write(dir.path().join("outside/victim"), "original").unwrap();
+    let configfs_path = configfs_path.to_str().unwrap();
+
+    for device in ["../../outside", "..", "/", ""] {
+        let err = attribute::set(configfs_path, VKMS_SUBSYSTEM, device, "victim", "changed")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{device}");
+    }
+    assert_eq!(fs::read_to_string(dir.path().join("outside/victim")).unwrap(), "original");
+}
+
+#[test]
+fn set_errors_name_the_attribute_and_value() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+    // A directory in place of the attribute makes the write fail
+    fs::create_dir_all(configfs.path().join("vkms/device/planes/plane0/type")).unwrap();
+
+    let err = attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", "planes/plane0/type", "2")
+        .unwrap_err();
+
+    assert!(err.to_string().starts_with("failed to set plane 'plane0' type to cursor: "));
+    assert_eq!(format!("{err:#}"), "failed to set plane 'plane0' type to cursor");
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(source.downcast_ref::<std::io::Error>().unwrap().kind(), ErrorKind::IsADirectory);
+    assert_eq!(fs::read_to_string(configfs.path().join("vkms/device/enabled")).unwrap(), "1");
+}
+
+#[test]
+fn set_returns_the_write_error_if_enabling_fails_too() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+    let device_path = configfs.path().join("vkms/device");
+    // A directory in place of the attribute makes the write fail, and a link
+    // outside the device makes enabling it again fail
+    fs::create_dir_all(device_path.join("planes/plane0/type")).unwrap();
+    fs::create_dir_all(device_path.join("planes/plane0/possible_crtcs")).unwrap();
+    std::os::unix::fs::symlink(configfs.path(), device_path.join("planes/plane0/possible_crtcs/x"))
+        .unwrap();
+
+    let err = attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", "planes/plane0/type", "2")
+        .unwrap_err();
+
+    assert_eq!(format!("{err:#}"), "failed to set plane 'plane0' type to cursor");
+    assert_eq!(fs::read_to_string(device_path.join("enabled")).unwrap(), "0");
+}
+
+#[test]
+fn set_writeback_toggles_existing_crtcs() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+    let set_writeback = |crtc, writeback| {
+        VkmsDevice::set_writeback(configfs_path, VKMS_SUBSYSTEM, "device", crtc, writeback)
+    };
+
+    let raw = |attribute| fs::read_to_string(configfs.path().join("vkms/device").join(attribute));
+
+    set_writeback("crtc0", true).unwrap();
+    assert_eq!(raw("crtcs/crtc0/writeback").unwrap(), "1");
+    assert_eq!(raw("enabled").unwrap(), "1");
+
+    set_writeback("crtc0", false).unwrap();
+    assert_eq!(raw("crtcs/crtc0/writeback").unwrap(), "0");
+
+    let err = set_writeback("missing", true).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert!(raw("crtcs/missing/writeback").is_err());
+}
+
+#[test]
+fn only_transient_errors_are_retried() {
+    let mut calls = 0;
+    let result = attribute::retry_transient(3, || {
+        calls += 1;
+        if calls < 3 {
+            Err(ErrorKind::ResourceBusy.into())
+        } else {
+            Ok(())
+        }
+    });
+    assert!(result.is_ok());
+    assert_eq!(calls, 3);
+
+    let mut calls = 0;
+    let err = attribute::retry_transient(3, || {
+        calls += 1;
+        Err(ErrorKind::InvalidInput.into())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(calls, 1);
+
+    let mut calls = 0;
+    let err = attribute::retry_transient(2, || {
+        calls += 1;
+        Err(ErrorKind::WouldBlock.into())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert_eq!(calls, 3);
+}
+
+#[test]
+fn set_enabled_rejects_links_outside_the_device() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+    let device_path = configfs.path().join("vkms/device");
+    fs::write(device_path.join("enabled"), "0").unwrap();
+    fs::create_dir_all(configfs.path().join("vkms/other/crtcs/crtc0")).unwrap();
+    fs::create_dir_all(device_path.join("planes/plane0/possible_crtcs")).unwrap();
+    std::os::unix::fs::symlink(
+        configfs.path().join("vkms/other/crtcs/crtc0"),
+        device_path.join("planes/plane0/possible_crtcs/crtc0"),
+    )
+    .unwrap();
+
+    let err = attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", "enabled", "true")
+        .unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("doesn't resolve to an item in crtcs of device device"));
+    assert_eq!(fs::read_to_string(device_path.join("enabled")).unwrap(), "0");
+
+    fs::remove_file(device_path.join("planes/plane0/possible_crtcs/crtc0")).unwrap();
+    std::os::unix::fs::symlink(
+        "../../../crtcs/crtc0",
+        device_path.join("planes/plane0/possible_crtcs/crtc0"),
+    )
+    .unwrap();
+    attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", "enabled", "true").unwrap();
+    assert_eq!(fs::read_to_string(device_path.join("enabled")).unwrap(), "1");
+}
+
+#[test]
+fn set_skips_unchanged_values() {
+    let configfs = enabled_configfs();
+    let configfs_path = configfs.path().to_str().unwrap();
+    let modified = |attribute| {
+        let path = configfs.path().join("vkms/device").join(attribute);
+        fs::metadata(path).unwrap().modified().unwrap()
+    };
+    let enabled_modified = modified("enabled");
+    let writeback_modified = modified("crtcs/crtc0/writeback");
+    std::thread::sleep(std::time::Duration::from_millis(10));
+
+    attribute::set(configfs_path, VKMS_SUBSYSTEM, "device", "crtcs/crtc0/writeback", "false")
+        .unwrap();
+
+    assert_eq!(modified("enabled"), enabled_modified);
+    assert_eq!(modified("crtcs/crtc0/writeback"), writeback_modified);
+}
@@ -0,0 +1,429 @@
+mod common;
+
+use std::process::Command;
+
+const CONFIGFS_MOCK : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/config-mock");
+
+fn vkmsctl(args : &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_vkmsctl"));
+    command.env_remove("VKMSCTL_CONFIGFS_PATH").env_remove("RUST_LOG").args(args);
+    command
+}
+
+#[test]
+fn config_succeeds() {
+    let status = vkmsctl(&["--configfs-path", CONFIGFS_MOCK, "config"]).status().unwrap();
+    assert_eq!(status.code(), Some(0));
+}
+
+#[test]
+fn missing_vkms_directory_means_no_devices() {
+    let configfs = tempfile::tempdir().unwrap();
+    let output = vkmsctl(&["--configfs-path", configfs.path().to_str().unwrap(), "config"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No VKMS devices configured"));
+}
+
+#[test]
+fn missing_configfs_exits_with_unavailable_code() {
+    let configfs = tempfile::tempdir().unwrap();
+    let missing = configfs.path().join("missing");
+    let status = vkmsctl(&["--configfs-path", missing.to_str().unwrap(), "config"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(6));
+}
+
+#[test]
+fn configfs_path_must_be_a_directory() {
+    let configfs = tempfile::tempdir().unwrap();
+    let file = configfs.path().join("file");
+    std::fs::write(&file, "").unwrap();
+
+    let output = vkmsctl(&["--configfs-path", file.to_str().unwrap(), "config"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("configfs path is not a directory"));
+}
+
+#[test]
+fn invalid_arguments_exit_with_usage_code() {
+    let status = vkmsctl(&["--no-such-flag"]).status().unwrap();
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn capabilities_without_vkms() {
+    let configfs = tempfile::tempdir().unwrap();
+    let output = vkmsctl(&["--configfs-path", configfs.path().to_str().unwrap(), "capabilities"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("ConfigFS devices: no"));
+}
+
+#[test]
+fn capabilities_output_file_is_not_created_when_probing_fails() {
+    // Unlike ConfigFS, a regular filesystem doesn't create the device groups
+    let configfs = tempfile::tempdir().unwrap();
+    std::fs::create_dir(configfs.path().join("vkms")).unwrap();
+    let file = configfs.path().join("capabilities.txt");
+
+    let configfs_path = configfs.path().to_str().unwrap();
+    let args = ["--configfs-path", configfs_path, "capabilities", "-o", file.to_str().unwrap()];
+    let output = vkmsctl(&args).output().unwrap();
+
+    assert_ne!(output.status.code(), Some(0));
+    assert!(!file.exists());
+}
+
+#[test]
+fn deny_warnings_fails_on_warnings() {
+    let configfs = common::fake_configfs(&[("orphan", "1")], &[]);
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let output = vkmsctl(&["--configfs-path", configfs_path, "config"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("plane orphan has no possible CRTCs"));
+
+    let status = vkmsctl(&["--configfs-path", configfs_path, "config", "--deny-warnings"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+
+    let status = vkmsctl(&[
+        "--configfs-path",
+        configfs_path,
+        "config",
+        "--deny-warnings",
+        "--allow-orphan-planes",
+        "--allow-no-crtcs",
+    ])
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(0));
+}
+
+#[test]
+fn config_summary_prints_totals() {
+    let output = vkmsctl(&["--configfs-path", CONFIGFS_MOCK, "config", "--summary"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Devices: 1 (1 enabled)"));
+    assert!(stdout.contains("Planes: 3 (1 overlay, 1 primary, 1 cursor)"));
+    assert!(stdout.contains("CRTCs: 1 (0 writeback)"));
+    assert!(stdout.contains("Connectors: 1 (1 connected, 0 disconnected, 0 unknown)"));
+    assert!(!stdout.contains("device1"));
+}
+
+#[test]
+fn keep_going_reads_every_device() {
+    let configfs = tempfile::tempdir().unwrap();
+    for (device, plane_type) in [("bad", "invalid"), ("good", "1")] {
+        common::fake_device(configfs.path(), device, &[("plane", plane_type)], &["crtc"]);
+    }
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let output = vkmsctl(&["--configfs-path", configfs_path, "config"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("good (disabled)"));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Error: Device bad: plane 'plane': invalid type value 'invalid'"));
+
+    let output = vkmsctl(&["--configfs-path", configfs_path, "config", "--keep-going"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("good (disabled)"));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Device bad: plane 'plane': invalid type value 'invalid'"));
+}
+
+#[test]
+fn output_flag_writes_to_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("config.txt");
+    let args = ["--configfs-path", CONFIGFS_MOCK, "config", "-o", file.to_str().unwrap()];
+    let output = vkmsctl(&args).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(std::fs::read_to_string(&file).unwrap().starts_with("device1 (enabled)"));
+}
+
+#[test]
+fn output_file_is_not_created_when_reading_fails() {
+    let configfs = tempfile::tempdir().unwrap();
+    common::fake_device(configfs.path(), "bad", &[("plane", "7")], &[]);
+    let file = configfs.path().join("config.txt");
+
+    let configfs_path = configfs.path().to_str().unwrap();
+    let args = ["--configfs-path", configfs_path, "config", "-o", file.to_str().unwrap()];
+    let output = vkmsctl(&args).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!file.exists());
+}
+
+#[test]
+fn dump_all_writes_every_device_as_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("backup.json");
+    let args = ["--configfs-path", CONFIGFS_MOCK, "dump-all", "-o", file.to_str().unwrap()];
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let dump : serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(dump["devices"][0]["name"], "device1");
+    assert_eq!(dump["devices"][0]["planes"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn names_with_spaces_are_supported() {
+    let configfs = tempfile::tempdir().unwrap();
+    let device_path =
+        common::fake_device(configfs.path(), "my device", &[("my plane", "1")], &["my crtc"]);
+    std::os::unix::fs::symlink(
+        "../../../crtcs/my crtc",
+        device_path.join("planes/my plane/possible_crtcs/my crtc"),
+    )
+    .unwrap();
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let output = vkmsctl(&["--configfs-path", configfs_path, "config"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("my device (disabled)"));
+    assert!(stdout.contains("my plane (primary) -> my crtc"));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("doesn't point to"));
+
+    let args = ["--configfs-path", configfs_path, "get", "my device", "planes/my plane/type"];
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("primary\n"));
+}
+
+#[test]
+fn config_format_prints_devices_as_data() {
+    let output = vkmsctl(&["--configfs-path", CONFIGFS_MOCK, "config", "--format", "json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"name\": \"device1\""));
+
+    let output = vkmsctl(&["--configfs-path", CONFIGFS_MOCK, "config", "--format", "ron"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("name: \"device1\""));
+    assert!(stdout.contains("type: cursor"));
+}
+
+#[test]
+fn timeout_aborts_hung_commands() {
+    let configfs = tempfile::tempdir().unwrap();
+    let device_path = configfs.path().join("vkms/device");
+    std::fs::create_dir_all(&device_path).unwrap();
+    // Reading a FIFO without a writer blocks forever
+    let status = Command::new("mkfifo").arg(device_path.join("hung")).status().unwrap();
+    assert!(status.success());
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    // --timeout is global, it can also follow the subcommand
+    for args in [
+        ["--configfs-path", configfs_path, "--timeout", "1", "get", "device", "hung"],
+        ["--configfs-path", configfs_path, "get", "device", "hung", "--timeout", "1"],
+    ] {
+        let output = vkmsctl(&args).output().unwrap();
+
+        assert_eq!(output.status.code(), Some(8));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("timed out after 1s"));
+    }
+}
+
+#[test]
+fn log_level_takes_precedence_over_rust_log() {
+    // A device without CRTCs produces a warning
+    let configfs = common::fake_configfs(&[], &[]);
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let warns = |rust_log : Option<&str>, args : &[&str]| {
+        let mut command = vkmsctl(&[&["--configfs-path", configfs_path, "config"], args].concat());
+        if let Some(rust_log) = rust_log {
+            command.env("RUST_LOG", rust_log);
+        }
+        let output = command.output().unwrap();
+        String::from_utf8_lossy(&output.stderr).contains("Warning: Device device")
+    };
+
+    assert!(warns(None, &[]));
+    assert!(!warns(None, &["--log-level", "error"]));
+    assert!(!warns(Some("error"), &[]));
+    assert!(!warns(Some("error"), &["-vv"]));
+    assert!(warns(Some("error"), &["--log-level", "warn"]));
+}
+
+#[test]
+fn remove_logs_elapsed_time_unless_quiet() {
+    for (quiet, logged) in [(false, true), (true, false)] {
+        // Unlike ConfigFS, a regular filesystem can only remove an empty device
+        let configfs = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(configfs.path().join("vkms/device")).unwrap();
+        let configfs_path = configfs.path().to_str().unwrap();
+
+        let mut args = vec!["--configfs-path", configfs_path, "remove", "device"];
+        if quiet {
+            args.push("--quiet");
+        }
+        let output = vkmsctl(&args).output().unwrap();
+
+        assert_eq!(output.status.code(), Some(0));
+        assert!(!configfs.path().join("vkms/device").exists());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(stderr.contains("Info: Removed device device in "), logged);
+        assert!(!stderr.contains("enabled attribute"));
+    }
+
+    let args = ["--configfs-path", CONFIGFS_MOCK, "remove", "missing"];
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "Error: Device missing doesn't exist\n");
+}
+
+#[test]
+fn remove_dry_run_prints_the_steps_without_removing() {
+    let configfs = tempfile::tempdir().unwrap();
+    let device_path = common::fake_device(configfs.path(), "device", &[], &["crtc0"]);
+    std::fs::write(device_path.join("enabled"), "1").unwrap();
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let args = ["--configfs-path", configfs_path, "remove", "device", "--dry-run"];
+    let output = vkmsctl(&args).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let device_path = device_path.display();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "write 0 to {device_path}/enabled\nrmdir {device_path}/crtcs/crtc0\n\
+             rmdir {device_path}\n"
+        )
+    );
+    assert_eq!(std::fs::read_to_string(configfs.path().join("vkms/device/enabled")).unwrap(), "1");
+}
+
+#[test]
+fn configfs_path_must_be_absolute() {
+    let mut command = vkmsctl(&["--configfs-path", "tests/config-mock", "config"]);
+    let output = command.current_dir(env!("CARGO_MANIFEST_DIR")).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("configfs path must be absolute"));
+
+    let args = ["--configfs-path", "tests/config-mock", "--allow-relative-configfs", "config"];
+    let status = vkmsctl(&args).current_dir(env!("CARGO_MANIFEST_DIR")).status().unwrap();
+    assert_eq!(status.code(), Some(0));
+}
+
+#[test]
+fn diff_reports_drift_from_a_dump() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("backup.json");
+    let file_str = file.to_str().unwrap();
+    let args = ["--configfs-path", CONFIGFS_MOCK, "dump-all", "-o", file_str];
+    assert_eq!(vkmsctl(&args).status().unwrap().code(), Some(0));
+
+    let args = ["--configfs-path", CONFIGFS_MOCK, "diff", "device1", file_str];
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No drift"));
+
+    let dump = std::fs::read_to_string(&file).unwrap();
+    std::fs::write(&file, dump.replace("\"type\": \"primary\"", "\"type\": \"overlay\"")).unwrap();
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("plane primary type: overlay -> primary"));
+}
+
+#[test]
+fn diff_reports_connector_status_drift() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("backup.json");
+    let file_str = file.to_str().unwrap();
+    let args = ["--configfs-path", CONFIGFS_MOCK, "dump-all", "-o", file_str];
+    assert_eq!(vkmsctl(&args).status().unwrap().code(), Some(0));
+
+    let dump = std::fs::read_to_string(&file).unwrap();
+    let dump = dump.replace("\"status\": \"connected\"", "\"status\": \"disconnected\"");
+    std::fs::write(&file, dump).unwrap();
+
+    let args = ["--configfs-path", CONFIGFS_MOCK, "diff", "device1", file_str];
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "connector connector1 status: disconnected -> connected\n");
+}
+
+#[test]
+fn verbose_errors_print_each_cause() {
+    let configfs = tempfile::tempdir().unwrap();
+    common::fake_device(configfs.path(), "bad", &[("plane", "7")], &[]);
+    let configfs_path = configfs.path().to_str().unwrap();
+
+    let output = vkmsctl(&["--configfs-path", configfs_path, "config"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: Device bad: plane 'plane': invalid type value '7'\n"));
+
+    let args = ["--configfs-path", configfs_path, "--verbose-errors", "config"];
+    let output = vkmsctl(&args).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr
+        .contains("Error: Device bad\nCaused by:\n  0: plane 'plane': invalid type value '7'\n"));
+}
+
+#[test]
+fn config_only_prints_the_selected_components() {
+    let args = ["--configfs-path", CONFIGFS_MOCK, "config", "--only", "connectors,crtcs"];
+    let output = vkmsctl(&args).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("  CRTCs:\n"));
+    assert!(stdout.contains("  Connectors:\n"));
+    assert!(!stdout.contains("Planes:"));
+    assert!(!stdout.contains("Encoders:"));
+
+    let args = ["--configfs-path", CONFIGFS_MOCK, "config", "--only", "planes", "--format", "json"];
+    let output = vkmsctl(&args).output().unwrap();
+    let dump : serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(dump["devices"][0]["planes"].as_array().unwrap().len(), 3);
+    assert!(dump["devices"][0].get("crtcs").is_none());
+
+    let args = ["--configfs-path", CONFIGFS_MOCK, "config", "--only", "crtcs", "--format", "ron"];
+    let output = vkmsctl(&args).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("crtcs: [") && !stdout.contains("planes"));
+
+    let args = ["--configfs-path", CONFIGFS_MOCK, "config", "--only", "planes,lasers"];
+    assert_eq!(vkmsctl(&args).status().unwrap().code(), Some(2));
+}
+
+#[test]
+fn out_of_space_exits_with_resources_code() {
+    // Writes to /dev/full fail with ENOSPC
+    let args = ["--configfs-path", CONFIGFS_MOCK, "config", "-o", "/dev/full"];
+    let output = vkmsctl(&args).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.ends_with(
+        "\nThe system is out of memory or space; this is not a configuration problem\n"
+    ));
+}
@@ -0,0 +1,44 @@
+//! Fake ConfigFS trees shared by the integration tests.
+
+// Each test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Creates the disabled device `name` in `<configfs>/vkms` with the given
+/// (name, type) planes and CRTCs, in the given order. Returns its path.
+pub fn fake_device(
+    configfs : &Path,
+    name : &str,
+    planes : &[(&str, &str)],
+    crtcs : &[&str],
+) -> PathBuf {
+    let device_path = configfs.join("vkms").join(name);
+
+    for group in ["planes", "crtcs", "encoders", "connectors"] {
+        fs::create_dir_all(device_path.join(group)).unwrap();
+    }
+    fs::write(device_path.join("enabled"), "0").unwrap();
+
+    for (name, plane_type) in planes {
+        let plane_path = device_path.join("planes").join(name);
+        fs::create_dir_all(plane_path.join("possible_crtcs")).unwrap();
+        fs::write(plane_path.join("type"), plane_type).unwrap();
+    }
+
+    for name in crtcs {
+        fs::create_dir_all(device_path.join("crtcs").join(name)).unwrap();
+    }
+
+    device_path
+}
+
+/// Creates a fake ConfigFS with a single device called "device", see
+/// `fake_device`.
+pub fn fake_configfs(planes : &[(&str, &str)], crtcs : &[&str]) -> TempDir {
+    let configfs = tempfile::tempdir().unwrap();
+    fake_device(configfs.path(), "device", planes, crtcs);
+    configfs
+}
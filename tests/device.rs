@@ -0,0 +1,459 @@
+mod common;
+
+use common::fake_configfs;
+use std::fs;
+use std::path::Path;
+use vkmsctl::device::{self, ConnectorStatus, PlaneKind, RemoveStep, ValidationWarning};
+use vkmsctl::device::{VkmsDevice, VKMS_SUBSYSTEM};
+
+const CONFIGFS_MOCK : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/config-mock");
+
+fn path_str(path : &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn from_fs_reads_mock() {
+    let device = VkmsDevice::from_fs(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "device1").unwrap();
+
+    assert!(device.enabled);
+    let planes : Vec<_> = device.planes.iter().map(|p| (p.name.as_str(), p.kind)).collect();
+    assert_eq!(
+        planes,
+        [
+            ("cursor", PlaneKind::Cursor),
+            ("overlay", PlaneKind::Overlay),
+            ("primary", PlaneKind::Primary),
+        ]
+    );
+    assert!(device.planes.iter().all(|p| p.possible_crtcs == ["crtc1"]));
+    assert_eq!(device.crtcs.len(), 1);
+    assert_eq!(device.crtcs[0].name, "crtc1");
+    assert!(!device.crtcs[0].writeback);
+    assert_eq!(device.orphan_planes().count(), 0);
+    assert_eq!(device.encoders.len(), 1);
+    assert_eq!(device.encoders[0].possible_crtcs, ["crtc1"]);
+    assert_eq!(device.connectors.len(), 1);
+    assert_eq!(device.connectors[0].possible_encoders, ["encoder1"]);
+    assert_eq!(device.connectors[0].status, ConnectorStatus::Connected);
+    assert_eq!(device.stranded_connectors().count(), 0);
+}
+
+#[test]
+fn orphan_planes_have_no_possible_crtcs() {
+    let configfs = fake_configfs(&[("linked", "1"), ("orphan", "0")], &["crtc0"]);
+    let planes_path = configfs.path().join("vkms/device/planes");
+    std::os::unix::fs::symlink(
+        configfs.path().join("vkms/device/crtcs/crtc0"),
+        planes_path.join("linked/possible_crtcs/crtc0"),
+    )
+    .unwrap();
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+    let orphans : Vec<_> = device.orphan_planes().map(|p| p.name.as_str()).collect();
+
+    assert_eq!(orphans, ["orphan"]);
+    assert_eq!(
+        device.validate(),
+        [ValidationWarning::OrphanPlane {
+            plane: "orphan".to_string()
+        }]
+    );
+}
+
+#[test]
+fn debug_output_is_independent_of_creation_order() {
+    let a = fake_configfs(&[("a", "1"), ("b", "0"), ("c", "2")], &["crtc0", "crtc1"]);
+    let b = fake_configfs(&[("c", "2"), ("b", "0"), ("a", "1")], &["crtc1", "crtc0"]);
+
+    let a = device::list(path_str(a.path()), VKMS_SUBSYSTEM).unwrap();
+    let b = device::list(path_str(b.path()), VKMS_SUBSYSTEM).unwrap();
+
+    assert_eq!(format!("{a:?}"), format!("{b:?}"));
+    assert_eq!(format!("{a:#?}"), format!("{b:#?}"));
+}
+
+#[test]
+fn list_reads_custom_subsystem() {
+    let configfs = fake_configfs(&[("plane", "1")], &["crtc"]);
+    fs::rename(configfs.path().join("vkms"), configfs.path().join("other")).unwrap();
+
+    let devices = device::list(path_str(configfs.path()), "other").unwrap();
+
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].name, "device");
+    assert!(device::list(path_str(configfs.path()), VKMS_SUBSYSTEM).unwrap().is_empty());
+}
+
+#[test]
+fn is_enabled_reads_enabled_attribute() {
+    let configfs = fake_configfs(&[], &[]);
+
+    assert!(VkmsDevice::is_enabled(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "device1").unwrap());
+    assert!(!VkmsDevice::is_enabled(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap());
+
+    let err = VkmsDevice::is_enabled(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "missing").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn from_fs_reads_crtc_writeback() {
+    let configfs = fake_configfs(&[], &["crtc0", "crtc1"]);
+    let crtcs_path = configfs.path().join("vkms/device/crtcs");
+    fs::write(crtcs_path.join("crtc0/writeback"), "1").unwrap();
+    fs::write(crtcs_path.join("crtc1/writeback"), "0").unwrap();
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+    let writeback : Vec<_> = device.crtcs.iter().map(|c| (c.name.as_str(), c.writeback)).collect();
+
+    assert_eq!(writeback, [("crtc0", true), ("crtc1", false)]);
+}
+
+#[test]
+fn list_skips_entries_that_are_not_directories() {
+    let configfs = fake_configfs(&[], &[]);
+    fs::write(configfs.path().join("vkms/stray"), "").unwrap();
+
+    let devices = device::list(path_str(configfs.path()), VKMS_SUBSYSTEM).unwrap();
+    let names : Vec<_> = devices.iter().map(|d| d.name.as_str()).collect();
+
+    assert_eq!(names, ["device"]);
+}
+
+#[test]
+fn stranded_connectors_dont_reach_a_crtc() {
+    let configfs = fake_configfs(&[], &["crtc0"]);
+    let device_path = configfs.path().join("vkms/device");
+    let link = |target : &str, link : &str| {
+        std::os::unix::fs::symlink(device_path.join(target), device_path.join(link)).unwrap();
+    };
+
+    for dir in [
+        "encoders/linked/possible_crtcs",
+        "encoders/unlinked/possible_crtcs",
+        "connectors/ok/possible_encoders",
+        "connectors/dead-end/possible_encoders",
+        "connectors/no-encoder/possible_encoders",
+    ] {
+        fs::create_dir_all(device_path.join(dir)).unwrap();
+    }
+    link("crtcs/crtc0", "encoders/linked/possible_crtcs/crtc0");
+    link("encoders/linked", "connectors/ok/possible_encoders/linked");
+    link("encoders/unlinked", "connectors/dead-end/possible_encoders/unlinked");
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+    let stranded : Vec<_> = device.stranded_connectors().map(|c| c.name.as_str()).collect();
+
+    assert_eq!(stranded, ["dead-end", "no-encoder"]);
+    assert_eq!(
+        device.validate(),
+        [
+            ValidationWarning::StrandedConnector {
+                connector: "dead-end".to_string()
+            },
+            ValidationWarning::StrandedConnector {
+                connector: "no-encoder".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn from_fs_resolves_absolute_and_relative_links() {
+    let planes = [("absolute", "1"), ("relative", "1"), ("slash", "1")];
+    let configfs = fake_configfs(&planes, &["crtc0"]);
+    let planes_path = configfs.path().join("vkms/device/planes");
+    let symlink = |target : &Path, plane : &str| {
+        std::os::unix::fs::symlink(target, planes_path.join(plane).join("possible_crtcs/crtc0"))
+            .unwrap();
+    };
+
+    symlink(&configfs.path().join("vkms/device/crtcs/crtc0"), "absolute");
+    symlink(Path::new("../../../crtcs/crtc0"), "relative");
+    symlink(Path::new("../../../crtcs/crtc0/"), "slash");
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+
+    assert!(device.planes.iter().all(|p| p.possible_crtcs == ["crtc0"]));
+}
+
+#[test]
+fn component_names_are_listed_without_parsing() {
+    let configfs = fake_configfs(&[("b", "invalid"), ("a", "invalid")], &["crtc0"]);
+    let configfs_path = path_str(configfs.path());
+    fs::remove_dir(configfs.path().join("vkms/device/connectors")).unwrap();
+
+    let names = |names : fn(&str, &str, &str) -> std::io::Result<Vec<String>>| {
+        names(configfs_path, VKMS_SUBSYSTEM, "device").unwrap()
+    };
+    assert_eq!(names(VkmsDevice::plane_names), ["a", "b"]);
+    assert_eq!(names(VkmsDevice::crtc_names), ["crtc0"]);
+    assert!(names(VkmsDevice::encoder_names).is_empty());
+    assert!(names(VkmsDevice::connector_names).is_empty());
+}
+
+#[test]
+fn devices_without_crtcs_are_reported() {
+    let configfs = fake_configfs(&[], &[]);
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+
+    assert_eq!(device.validate(), [ValidationWarning::NoCrtcs]);
+}
+
+#[test]
+fn devices_round_trip_through_serde() {
+    let device = VkmsDevice::from_fs(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "device1").unwrap();
+
+    let json = serde_json::to_value(&device).unwrap();
+    assert_eq!(json["planes"][0]["type"], "cursor");
+    assert_eq!(json["crtcs"][0]["writeback"], false);
+
+    let parsed : VkmsDevice = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, device);
+}
+
+#[test]
+fn lint_warns_about_crowded_crtcs() {
+    let configfs = fake_configfs(
+        &[("primary-a", "1"), ("primary-b", "1"), ("cursor", "2"), ("overlay", "0")],
+        &["crtc0"],
+    );
+    let planes_path = configfs.path().join("vkms/device/planes");
+    for plane in ["primary-a", "primary-b", "cursor", "overlay"] {
+        std::os::unix::fs::symlink(
+            configfs.path().join("vkms/device/crtcs/crtc0"),
+            planes_path.join(plane).join("possible_crtcs/crtc0"),
+        )
+        .unwrap();
+    }
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+
+    assert_eq!(
+        device.lint(),
+        [ValidationWarning::CrowdedCrtc {
+            crtc: "crtc0".to_string(),
+            kind: PlaneKind::Primary,
+            planes: vec!["primary-a".to_string(), "primary-b".to_string()],
+        }]
+    );
+    assert!(device.validate().is_empty());
+}
+
+#[test]
+fn attribute_paths_point_into_the_device() {
+    let device_path = VkmsDevice::device_path(CONFIGFS_MOCK, VKMS_SUBSYSTEM, "device1");
+
+    assert_eq!(device_path, Path::new(CONFIGFS_MOCK).join("vkms/device1"));
+    assert!(VkmsDevice::plane_type_path(&device_path, "primary").is_file());
+    assert!(VkmsDevice::plane_possible_crtcs_path(&device_path, "primary").is_dir());
+    assert!(VkmsDevice::encoder_possible_crtcs_path(&device_path, "encoder1").is_dir());
+    assert!(VkmsDevice::connector_possible_encoders_path(&device_path, "connector1").is_dir());
+    assert_eq!(
+        VkmsDevice::crtc_writeback_path(&device_path, "crtc1"),
+        device_path.join("crtcs/crtc1/writeback")
+    );
+    assert_eq!(
+        VkmsDevice::connector_status_path(&device_path, "connector1"),
+        device_path.join("connectors/connector1/status")
+    );
+}
+
+#[test]
+fn validate_warns_about_writeback_connector_names() {
+    let configfs = fake_configfs(&[], &["crtc0", "crtc1"]);
+    let device_path = configfs.path().join("vkms/device");
+    fs::write(device_path.join("crtcs/crtc0/writeback"), "0").unwrap();
+    fs::write(device_path.join("crtcs/crtc1/writeback"), "1").unwrap();
+    fs::create_dir(device_path.join("connectors/Writeback-1")).unwrap();
+    fs::create_dir(device_path.join("connectors/Writeback-2")).unwrap();
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+    let clashes : Vec<_> = device
+        .validate()
+        .into_iter()
+        .filter(|warning| matches!(warning, ValidationWarning::WritebackNameClash { .. }))
+        .collect();
+
+    assert_eq!(
+        clashes,
+        [ValidationWarning::WritebackNameClash {
+            connector: "Writeback-1".to_string(),
+            crtc: "crtc1".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn from_fs_reads_optional_plane_capabilities() {
+    let configfs = fake_configfs(&[("plane", "1"), ("old", "0")], &["crtc0"]);
+    let plane_path = configfs.path().join("vkms/device/planes/plane");
+    fs::write(plane_path.join("supported_formats"), "XR24 AR24\nRG16\n").unwrap();
+    fs::write(plane_path.join("supported_rotations"), "0x3f\n").unwrap();
+
+    let device = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+
+    assert!(device.planes[0].formats.is_empty());
+    assert_eq!(device.planes[0].rotations, None);
+    assert_eq!(device.planes[1].formats, ["XR24", "AR24", "RG16"]);
+    assert_eq!(device.planes[1].rotations, Some(0x3f));
+
+    let json = serde_json::to_value(&device).unwrap();
+    assert!(json["planes"][0].get("formats").is_none());
+    assert_eq!(serde_json::from_value::<VkmsDevice>(json).unwrap(), device);
+}
+
+#[test]
+fn invalid_attributes_are_reported_with_their_value() {
+    let configfs = fake_configfs(&[("plane0", "7\n")], &[]);
+    let err = VkmsDevice::from_fs(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "plane 'plane0': invalid type value '7'");
+
+    let configfs = fake_configfs(&[], &[]);
+    let connector_path = configfs.path().join("vkms/device/connectors/connector0");
+    fs::create_dir(&connector_path).unwrap();
+    fs::write(connector_path.join("status"), "").unwrap();
+    let err = device::list(path_str(configfs.path()), VKMS_SUBSYSTEM).unwrap_err();
+    assert_eq!(err.to_string(), "Device device: connector 'connector0': invalid status value ''");
+    assert_eq!(format!("{err:#}"), "Device device");
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(source.to_string(), "connector 'connector0': invalid status value ''");
+}
+
+#[test]
+fn missing_enabled_attribute_means_disabled() {
+    let configfs = fake_configfs(&[("plane", "1")], &["crtc"]);
+    fs::remove_file(configfs.path().join("vkms/device/enabled")).unwrap();
+
+    let devices = device::list(path_str(configfs.path()), VKMS_SUBSYSTEM).unwrap();
+
+    assert_eq!(devices.len(), 1);
+    assert!(!devices[0].enabled);
+}
+
+#[test]
+fn remove_unlinks_devices_that_cant_be_read() {
+    let configfs = fake_configfs(&[("plane0", "7\n")], &["crtc0"]);
+    let configfs_path = path_str(configfs.path());
+    let device_path = configfs.path().join("vkms/device");
+    fs::write(device_path.join("enabled"), "1").unwrap();
+
+    let encoder_path = device_path.join("encoders/encoder0");
+    let connector_path = device_path.join("connectors/connector0");
+    fs::create_dir_all(encoder_path.join("possible_crtcs")).unwrap();
+    fs::create_dir_all(connector_path.join("possible_encoders")).unwrap();
+    let links = [
+        (device_path.join("crtcs/crtc0"), device_path.join("planes/plane0/possible_crtcs/crtc0")),
+        (device_path.join("crtcs/crtc0"), encoder_path.join("possible_crtcs/crtc0")),
+        (encoder_path.clone(), connector_path.join("possible_encoders/encoder0")),
+    ];
+    for (target, link) in &links {
+        std::os::unix::fs::symlink(target, link).unwrap();
+    }
+
+    let err = device::remove(configfs_path, VKMS_SUBSYSTEM, "missing").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    let outside = configfs.path().join("outside");
+    fs::create_dir_all(outside.join("planes/plane/possible_crtcs")).unwrap();
+    fs::write(outside.join("planes/plane/possible_crtcs/file"), "").unwrap();
+    let err = device::remove(configfs_path, VKMS_SUBSYSTEM, "../outside").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(outside.join("planes/plane/possible_crtcs/file").exists());
+
+    // A regular filesystem, unlike ConfigFS, can't remove directories with
+    // attributes, so only the steps before removing the components can be
+    // checked
+    let err = device::remove(configfs_path, VKMS_SUBSYSTEM, "device").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::DirectoryNotEmpty);
+    assert_eq!(fs::read_to_string(device_path.join("enabled")).unwrap(), "0");
+    for (_, link) in &links {
+        assert!(fs::symlink_metadata(link).is_err(), "{} not removed", link.display());
+    }
+}
+
+#[test]
+fn wait_removed_polls_until_the_device_is_gone() {
+    let configfs = fake_configfs(&[], &[]);
+    let configfs_path = path_str(configfs.path());
+    let timeout = std::time::Duration::from_millis(50);
+
+    let err = device::wait_removed(configfs_path, VKMS_SUBSYSTEM, "device", timeout).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    let device_path = configfs.path().join("vkms/device");
+    let remover = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::remove_dir_all(device_path).unwrap();
+    });
+    let timeout = std::time::Duration::from_secs(5);
+    device::wait_removed(configfs_path, VKMS_SUBSYSTEM, "device", timeout).unwrap();
+    remover.join().unwrap();
+
+    device::wait_removed(configfs_path, VKMS_SUBSYSTEM, "missing", timeout).unwrap();
+}
+
+#[test]
+fn attribute_encodings_round_trip() {
+    for kind in PlaneKind::ALL {
+        assert_eq!(PlaneKind::from_attribute(kind.to_attribute()), Some(kind));
+    }
+    for status in ConnectorStatus::ALL {
+        assert_eq!(ConnectorStatus::from_attribute(status.to_attribute()), Some(status));
+    }
+
+    assert_eq!(PlaneKind::Overlay.to_attribute(), "0");
+    assert_eq!(PlaneKind::Primary.to_attribute(), "1");
+    assert_eq!(PlaneKind::Cursor.to_attribute(), "2");
+    assert_eq!(ConnectorStatus::Connected.to_attribute(), "1");
+    assert_eq!(ConnectorStatus::Disconnected.to_attribute(), "2");
+    assert_eq!(ConnectorStatus::Unknown.to_attribute(), "3");
+}
+
+#[test]
+fn remove_steps_list_the_removal_in_order() {
+    let configfs = fake_configfs(&[("plane0", "1")], &["crtc0"]);
+    let device_path = configfs.path().join("vkms/device");
+    fs::write(device_path.join("enabled"), "1").unwrap();
+    let link = device_path.join("planes/plane0/possible_crtcs/crtc0");
+    std::os::unix::fs::symlink(device_path.join("crtcs/crtc0"), &link).unwrap();
+
+    let steps = device::remove_steps(path_str(configfs.path()), VKMS_SUBSYSTEM, "device").unwrap();
+
+    assert_eq!(
+        steps,
+        [
+            RemoveStep::Disable(device_path.join("enabled")),
+            RemoveStep::Unlink(link.clone()),
+            RemoveStep::RemoveDir(device_path.join("planes/plane0")),
+            RemoveStep::RemoveDir(device_path.join("crtcs/crtc0")),
+            RemoveStep::RemoveDir(device_path.clone()),
+        ]
+    );
+    assert_eq!(steps[1].to_string(), format!("unlink {}", link.display()));
+    assert_eq!(fs::read_to_string(device_path.join("enabled")).unwrap(), "1");
+    assert!(fs::symlink_metadata(&link).is_ok());
+}
+
+#[test]
+fn devices_outside_the_subsystem_are_rejected() {
+    fn kind<T>(result : std::io::Result<T>) -> Option<std::io::ErrorKind> {
+        result.err().map(|e| e.kind())
+    }
+
+    let configfs = fake_configfs(&[], &[]);
+    let configfs_path = path_str(configfs.path());
+    let timeout = std::time::Duration::from_millis(50);
+    let invalid = Some(std::io::ErrorKind::InvalidInput);
+
+    for name in ["..", "../vkms/device", "/", ""] {
+        assert_eq!(kind(VkmsDevice::from_fs(configfs_path, VKMS_SUBSYSTEM, name)), invalid);
+        assert_eq!(kind(VkmsDevice::is_enabled(configfs_path, VKMS_SUBSYSTEM, name)), invalid);
+        assert_eq!(kind(VkmsDevice::plane_names(configfs_path, VKMS_SUBSYSTEM, name)), invalid);
+        assert_eq!(kind(VkmsDevice::check_links(configfs_path, VKMS_SUBSYSTEM, name)), invalid);
+        let wait_removed = device::wait_removed(configfs_path, VKMS_SUBSYSTEM, name, timeout);
+        assert_eq!(kind(wait_removed), invalid);
+    }
+}
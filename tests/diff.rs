@@ -0,0 +1,185 @@
+use vkmsctl::device::{Connector, ConnectorStatus, Crtc, Encoder, Plane, PlaneKind, VkmsDevice};
+use vkmsctl::diff::Change;
+
+fn device(planes : &[(&str, PlaneKind)], crtcs : &[(&str, bool)]) -> VkmsDevice {
+    VkmsDevice {
+        name: "device".to_string(),
+        enabled: true,
+        planes: planes
+            .iter()
+            .map(|(name, kind)| Plane {
+                name: name.to_string(),
+                kind: *kind,
+                possible_crtcs: vec!["crtc0".to_string()],
+                formats: Vec::new(),
+                rotations: None,
+            })
+            .collect(),
+        crtcs: crtcs
+            .iter()
+            .map(|(name, writeback)| Crtc {
+                name: name.to_string(),
+                writeback: *writeback,
+            })
+            .collect(),
+        encoders: Vec::new(),
+        connectors: Vec::new(),
+    }
+}
+
+fn encoder(name : &str, possible_crtcs : &[&str]) -> Encoder {
+    Encoder {
+        name: name.to_string(),
+        possible_crtcs: possible_crtcs.iter().map(|crtc| crtc.to_string()).collect(),
+    }
+}
+
+fn connector(name : &str, status : ConnectorStatus, possible_encoders : &[&str]) -> Connector {
+    Connector {
+        name: name.to_string(),
+        status,
+        possible_encoders: possible_encoders.iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+#[test]
+fn diff_of_identical_devices_is_empty() {
+    let a = device(&[("primary", PlaneKind::Primary)], &[("crtc0", false)]);
+    let b = device(&[("primary", PlaneKind::Primary)], &[("crtc0", false)]);
+
+    let diff = a.diff(&b);
+
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "no changes");
+}
+
+#[test]
+fn diff_reports_every_change() {
+    let a = device(
+        &[("primary", PlaneKind::Primary), ("old", PlaneKind::Overlay)],
+        &[("crtc0", false)],
+    );
+    let mut b = device(
+        &[
+            ("primary", PlaneKind::Cursor),
+            ("new0", PlaneKind::Overlay),
+            ("new1", PlaneKind::Overlay),
+        ],
+        &[("crtc0", true), ("crtc1", false)],
+    );
+    b.enabled = false;
+
+    let diff = a.diff(&b);
+
+    assert_eq!(
+        diff.changes,
+        [
+            Change::Enabled { from: true, to: false },
+            Change::PlaneRemoved { name: "old".to_string() },
+            Change::PlaneRetyped {
+                name: "primary".to_string(),
+                from: PlaneKind::Primary,
+                to: PlaneKind::Cursor,
+            },
+            Change::PlaneAdded { name: "new0".to_string() },
+            Change::PlaneAdded { name: "new1".to_string() },
+            Change::CrtcWriteback {
+                name: "crtc0".to_string(),
+                from: false,
+                to: true,
+            },
+            Change::CrtcAdded { name: "crtc1".to_string() },
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "1 enabled flag changed, 1 plane removed, 1 plane retyped, 2 planes added, \
+         1 CRTC writeback toggled, 1 CRTC added"
+    );
+}
+
+#[test]
+fn diff_reports_relinked_planes() {
+    let a = device(&[("primary", PlaneKind::Primary)], &[("crtc0", false), ("crtc1", false)]);
+    let mut b = device(&[("primary", PlaneKind::Primary)], &[("crtc0", false), ("crtc1", false)]);
+    b.planes[0].possible_crtcs = vec!["crtc0".to_string(), "crtc1".to_string()];
+
+    let diff = a.diff(&b);
+
+    assert_eq!(
+        diff.changes,
+        [Change::PlaneRelinked {
+            name: "primary".to_string(),
+            from: vec!["crtc0".to_string()],
+            to: vec!["crtc0".to_string(), "crtc1".to_string()],
+        }]
+    );
+    assert_eq!(
+        diff.changes[0].to_string(),
+        "plane primary possible_crtcs: [crtc0] -> [crtc0, crtc1]"
+    );
+    assert_eq!(diff.to_string(), "1 plane relinked");
+}
+
+#[test]
+fn diff_reports_encoder_and_connector_changes() {
+    let mut a = device(&[], &[("crtc0", false)]);
+    a.encoders = vec![encoder("encoder0", &["crtc0"]), encoder("old", &["crtc0"])];
+    a.connectors = vec![
+        connector("connector0", ConnectorStatus::Connected, &["encoder0"]),
+        connector("connector1", ConnectorStatus::Connected, &["encoder0"]),
+        connector("old", ConnectorStatus::Connected, &["encoder0"]),
+    ];
+    let mut b = device(&[], &[("crtc0", false)]);
+    b.encoders = vec![encoder("encoder0", &[]), encoder("new", &["crtc0"])];
+    b.connectors = vec![
+        connector("connector0", ConnectorStatus::Disconnected, &["encoder0"]),
+        connector("connector1", ConnectorStatus::Connected, &["encoder0", "new"]),
+        connector("new", ConnectorStatus::Connected, &["new"]),
+    ];
+
+    let diff = a.diff(&b);
+
+    assert_eq!(
+        diff.changes,
+        [
+            Change::EncoderRelinked {
+                name: "encoder0".to_string(),
+                from: vec!["crtc0".to_string()],
+                to: Vec::new(),
+            },
+            Change::EncoderRemoved { name: "old".to_string() },
+            Change::EncoderAdded { name: "new".to_string() },
+            Change::ConnectorStatusChanged {
+                name: "connector0".to_string(),
+                from: ConnectorStatus::Connected,
+                to: ConnectorStatus::Disconnected,
+            },
+            Change::ConnectorRelinked {
+                name: "connector1".to_string(),
+                from: vec!["encoder0".to_string()],
+                to: vec!["encoder0".to_string(), "new".to_string()],
+            },
+            Change::ConnectorRemoved { name: "old".to_string() },
+            Change::ConnectorAdded { name: "new".to_string() },
+        ]
+    );
+    let lines : Vec<_> = diff.changes.iter().map(|change| change.to_string()).collect();
+    assert_eq!(
+        lines,
+        [
+            "encoder encoder0 possible_crtcs: [crtc0] -> []",
+            "encoder old removed",
+            "encoder new added",
+            "connector connector0 status: connected -> disconnected",
+            "connector connector1 possible_encoders: [encoder0] -> [encoder0, new]",
+            "connector old removed",
+            "connector new added",
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "1 encoder relinked, 1 encoder removed, 1 encoder added, 1 connector status changed, \
+         1 connector relinked, 1 connector removed, 1 connector added"
+    );
+}
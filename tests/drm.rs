@@ -0,0 +1,143 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+use vkmsctl::device::{Connector, ConnectorStatus, VkmsDevice};
+use vkmsctl::drm::DrmConnector;
+
+fn device(connectors : &[&str]) -> VkmsDevice {
+    VkmsDevice {
+        name: "device".to_string(),
+        enabled: true,
+        planes: Vec::new(),
+        crtcs: Vec::new(),
+        encoders: Vec::new(),
+        connectors: connectors
+            .iter()
+            .map(|name| Connector {
+                name: name.to_string(),
+                status: ConnectorStatus::Connected,
+                possible_encoders: Vec::new(),
+            })
+            .collect(),
+    }
+}
+
+/// Creates a fake /sys/class/drm with card0 for another device and card1 for
+/// "device", with the given (name, connector_id, status) connectors.
+fn fake_sysfs(connectors : &[(&str, &str, &str)]) -> tempfile::TempDir {
+    let sysfs = tempfile::tempdir().unwrap();
+    for (card, device) in [("card0", "other"), ("card1", "device")] {
+        fs::create_dir(sysfs.path().join(card)).unwrap();
+        symlink(
+            format!("../../devices/platform/{device}"),
+            sysfs.path().join(card).join("device"),
+        )
+        .unwrap();
+    }
+    fs::create_dir(sysfs.path().join("card0-Virtual-1")).unwrap();
+
+    for (name, id, status) in connectors {
+        let path = sysfs.path().join(format!("card1-{name}"));
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("connector_id"), id).unwrap();
+        fs::write(path.join("status"), status).unwrap();
+    }
+
+    sysfs
+}
+
+#[test]
+fn drm_connectors_are_matched_in_order() {
+    let sysfs = fake_sysfs(&[
+        ("Virtual-2", "52\n", "disconnected\n"),
+        ("Virtual-1", "51\n", "connected\n"),
+    ]);
+    let device = device(&["a", "b"]);
+
+    assert_eq!(
+        device.drm_connectors(sysfs.path().to_str().unwrap()),
+        [
+            DrmConnector {
+                configfs_name: "a".to_string(),
+                name: "Virtual-1".to_string(),
+                id: Some(51),
+                status: ConnectorStatus::Connected,
+            },
+            DrmConnector {
+                configfs_name: "b".to_string(),
+                name: "Virtual-2".to_string(),
+                id: Some(52),
+                status: ConnectorStatus::Disconnected,
+            },
+        ]
+    );
+}
+
+#[test]
+fn drm_connector_ids_are_looked_up_by_configfs_name() {
+    let sysfs = fake_sysfs(&[("Virtual-1", "51\n", "connected\n"), ("Virtual-2", "52\n", "")]);
+    let sysfs_path = sysfs.path().to_str().unwrap();
+    let device = device(&["a", "b"]);
+
+    assert_eq!(device.drm_connector_id(sysfs_path, "a"), Some(51));
+    assert_eq!(device.drm_connector_id(sysfs_path, "b"), Some(52));
+    assert_eq!(device.drm_connector_id(sysfs_path, "missing"), None);
+
+    // Kernels that don't expose the ID
+    fs::remove_file(sysfs.path().join("card1-Virtual-1/connector_id")).unwrap();
+    fs::remove_file(sysfs.path().join("card1-Virtual-2/connector_id")).unwrap();
+    assert_eq!(device.drm_connector_id(sysfs_path, "a"), None);
+}
+
+#[test]
+fn drm_connectors_are_empty_without_a_match() {
+    let sysfs = fake_sysfs(&[("Virtual-1", "51\n", "connected\n")]);
+
+    assert!(device(&["a", "b"]).drm_connectors(sysfs.path().to_str().unwrap()).is_empty());
+
+    let mut other = device(&["a"]);
+    other.name = "missing".to_string();
+    assert!(other.drm_connectors(sysfs.path().to_str().unwrap()).is_empty());
+}
+
+#[test]
+fn drm_writeback_connectors_are_skipped() {
+    let sysfs = fake_sysfs(&[
+        ("Writeback-1", "53\n", "unknown\n"),
+        ("Virtual-1", "51\n", "connected\n"),
+    ]);
+
+    assert_eq!(
+        device(&["a"]).drm_connectors(sysfs.path().to_str().unwrap()),
+        [DrmConnector {
+            configfs_name: "a".to_string(),
+            name: "Virtual-1".to_string(),
+            id: Some(51),
+            status: ConnectorStatus::Connected,
+        }]
+    );
+}
+
+#[test]
+fn drm_connectors_without_ids_are_matched_by_number() {
+    let names : Vec<_> = (1..=11).map(|n| format!("Virtual-{n}")).collect();
+    let connectors : Vec<_> = names.iter().map(|name| (name.as_str(), "", "connected\n")).collect();
+    let sysfs = fake_sysfs(&connectors);
+    for name in &names {
+        fs::remove_file(sysfs.path().join(format!("card1-{name}/connector_id"))).unwrap();
+    }
+    let configfs_names : Vec<_> = (1..=11).map(|n| format!("connector{n:02}")).collect();
+    let configfs_names : Vec<_> = configfs_names.iter().map(String::as_str).collect();
+
+    let drm_connectors = device(&configfs_names).drm_connectors(sysfs.path().to_str().unwrap());
+
+    let pairs : Vec<_> = drm_connectors
+        .iter()
+        .map(|connector| (connector.configfs_name.as_str(), connector.name.as_str(), connector.id))
+        .collect();
+    let expected : Vec<_> = configfs_names
+        .iter()
+        .zip(&names)
+        .map(|(configfs_name, name)| (*configfs_name, name.as_str(), None))
+        .collect();
+    assert_eq!(pairs, expected);
+}